@@ -0,0 +1,93 @@
+fn main() {
+    let mut input = String::new();
+    let mut buffer = String::new();
+    loop {
+        buffer.clear();
+        match std::io::stdin().read_line(&mut buffer) {
+            Ok(0) => {
+                break;
+            }
+            Ok(_) => {
+                buffer.chars().for_each(|c| input.push(c));
+            }
+            Err(e) => panic!("{}", e),
+        }
+    }
+    let elves = parser::elves(&input).unwrap().1;
+    let totals: Vec<usize> = elves.iter().map(|e| e.iter().sum()).collect();
+
+    let ans1 = top_k(&totals, 1).iter().sum::<usize>();
+    println!("Part One: {}", ans1);
+    let ans2 = top_k(&totals, 3).iter().sum::<usize>();
+    println!("Part Two: {}", ans2);
+
+    let args: Vec<_> = std::env::args().skip(1).collect();
+    if let Some(k) = args.iter().find_map(|a| a.strip_prefix("--top=")) {
+        let k = k.parse().expect("Invalid value for --top=");
+        println!("Top {}: {:?}", k, top_k(&totals, k));
+    }
+}
+
+fn top_k(totals: &[usize], k: usize) -> Vec<usize> {
+    let mut result = totals.to_vec();
+    result.sort_unstable_by(|a, b| b.cmp(a));
+    result.truncate(k);
+    result
+}
+
+mod parser {
+    use nom::{
+        bytes::complete::tag,
+        character::complete::digit1,
+        combinator::map_res,
+        multi::{separated_list0, separated_list1},
+        IResult,
+    };
+
+    fn calories(input: &str) -> IResult<&str, usize> {
+        map_res(digit1, |s: &str| s.parse::<usize>())(input)
+    }
+    fn elf(input: &str) -> IResult<&str, Vec<usize>> {
+        separated_list1(tag("\n"), calories)(input)
+    }
+    pub fn elves(input: &str) -> IResult<&str, Vec<Vec<usize>>> {
+        separated_list0(tag("\n\n"), elf)(input)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_calories() {
+            assert_eq!(calories("1000\n"), Ok(("\n", 1000)));
+        }
+        #[test]
+        fn test_elf() {
+            assert_eq!(
+                elf("1000\n2000\n\n3000"),
+                Ok(("\n\n3000", vec![1000, 2000]))
+            );
+        }
+        #[test]
+        fn test_elves() {
+            assert_eq!(
+                elves("1000\n2000\n\n4000\n\n5000\n6000\n"),
+                Ok(("\n", vec![vec![1000, 2000], vec![4000], vec![5000, 6000]]))
+            );
+        }
+        #[test]
+        fn test_sample() {
+            let input = include_str!("../../input/01-sample.txt");
+            let totals: Vec<usize> = elves(input)
+                .unwrap()
+                .1
+                .iter()
+                .map(|e| e.iter().sum())
+                .collect();
+            assert_eq!(totals, vec![6000, 4000, 11000, 24000, 10000]);
+            assert_eq!(crate::top_k(&totals, 3), vec![24000, 11000, 10000]);
+            assert_eq!(crate::top_k(&totals, 10).len(), 5);
+        }
+    }
+}
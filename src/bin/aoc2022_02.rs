@@ -0,0 +1,196 @@
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shape {
+    Rock,
+    Paper,
+    Scissors,
+}
+impl FromStr for Shape {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "A" | "X" => Ok(Shape::Rock),
+            "B" | "Y" => Ok(Shape::Paper),
+            "C" | "Z" => Ok(Shape::Scissors),
+            _ => Err("error"),
+        }
+    }
+}
+impl Shape {
+    fn score(&self) -> usize {
+        match self {
+            Shape::Rock => 1,
+            Shape::Paper => 2,
+            Shape::Scissors => 3,
+        }
+    }
+    fn beats(&self) -> Shape {
+        match self {
+            Shape::Rock => Shape::Scissors,
+            Shape::Paper => Shape::Rock,
+            Shape::Scissors => Shape::Paper,
+        }
+    }
+    fn outcome_against(&self, opponent: Shape) -> Outcome {
+        if self.beats() == opponent {
+            Outcome::Win
+        } else if opponent.beats() == *self {
+            Outcome::Lose
+        } else {
+            Outcome::Draw
+        }
+    }
+    fn for_outcome(opponent: Shape, outcome: Outcome) -> Shape {
+        match outcome {
+            Outcome::Lose => opponent.beats(),
+            Outcome::Draw => opponent,
+            Outcome::Win => opponent.beats().beats(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Lose,
+    Draw,
+    Win,
+}
+impl FromStr for Outcome {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "X" => Ok(Outcome::Lose),
+            "Y" => Ok(Outcome::Draw),
+            "Z" => Ok(Outcome::Win),
+            _ => Err("error"),
+        }
+    }
+}
+impl Outcome {
+    fn score(&self) -> usize {
+        match self {
+            Outcome::Lose => 0,
+            Outcome::Draw => 3,
+            Outcome::Win => 6,
+        }
+    }
+}
+
+fn round_score(opponent: Shape, mine: Shape) -> usize {
+    mine.score() + mine.outcome_against(opponent).score()
+}
+
+fn main() {
+    let mut input = String::new();
+    let mut buffer = String::new();
+    loop {
+        buffer.clear();
+        match std::io::stdin().read_line(&mut buffer) {
+            Ok(0) => {
+                break;
+            }
+            Ok(_) => {
+                buffer.chars().for_each(|c| input.push(c));
+            }
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    let ans1: usize = parser::rounds::<Shape>(&input)
+        .unwrap()
+        .1
+        .into_iter()
+        .map(|(opponent, mine)| round_score(opponent, mine))
+        .sum();
+    println!("Part One: {}", ans1);
+    let ans2: usize = parser::rounds::<Outcome>(&input)
+        .unwrap()
+        .1
+        .into_iter()
+        .map(|(opponent, outcome)| round_score(opponent, Shape::for_outcome(opponent, outcome)))
+        .sum();
+    println!("Part Two: {}", ans2);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHAPES: [Shape; 3] = [Shape::Rock, Shape::Paper, Shape::Scissors];
+
+    #[test]
+    fn every_shape_combination() {
+        use Outcome::*;
+        let expected = [[Draw, Lose, Win], [Win, Draw, Lose], [Lose, Win, Draw]];
+        for (i, mine) in SHAPES.iter().enumerate() {
+            for (j, opponent) in SHAPES.iter().enumerate() {
+                let outcome = mine.outcome_against(*opponent);
+                assert_eq!(outcome, expected[i][j], "{:?} vs {:?}", mine, opponent);
+                assert_eq!(Shape::for_outcome(*opponent, outcome), *mine);
+                assert_eq!(
+                    round_score(*opponent, *mine),
+                    mine.score() + outcome.score()
+                );
+            }
+        }
+    }
+    #[test]
+    fn sample_scores() {
+        assert_eq!(round_score(Shape::Rock, Shape::Paper), 8);
+        assert_eq!(round_score(Shape::Paper, Shape::Rock), 1);
+        assert_eq!(round_score(Shape::Scissors, Shape::Scissors), 6);
+    }
+}
+
+mod parser {
+    use super::*;
+    use nom::{
+        bytes::complete::tag,
+        character::complete::one_of,
+        combinator::map_res,
+        multi::separated_list0,
+        sequence::{preceded, tuple},
+        IResult,
+    };
+
+    fn opponent(input: &str) -> IResult<&str, Shape> {
+        map_res(one_of("ABC"), |c| Shape::from_str(&c.to_string()))(input)
+    }
+    fn response<T: FromStr>(input: &str) -> IResult<&str, T> {
+        map_res(one_of("XYZ"), |c| T::from_str(&c.to_string()))(input)
+    }
+    fn round<T: FromStr>(input: &str) -> IResult<&str, (Shape, T)> {
+        tuple((opponent, preceded(tag(" "), response)))(input)
+    }
+    pub fn rounds<T: FromStr>(input: &str) -> IResult<&str, Vec<(Shape, T)>> {
+        separated_list0(tag("\n"), round)(input)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_round() {
+            assert_eq!(round("A Y"), Ok(("", (Shape::Rock, Shape::Paper))));
+            assert_eq!(round("C X"), Ok(("", (Shape::Scissors, Outcome::Lose))));
+            assert!(round::<Shape>("X A").is_err());
+        }
+        #[test]
+        fn test_rounds() {
+            let input = include_str!("../../input/02-sample.txt");
+            assert_eq!(
+                rounds(input),
+                Ok((
+                    "\n",
+                    vec![
+                        (Shape::Rock, Outcome::Draw),
+                        (Shape::Paper, Outcome::Lose),
+                        (Shape::Scissors, Outcome::Win)
+                    ]
+                ))
+            );
+        }
+    }
+}
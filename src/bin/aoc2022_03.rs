@@ -0,0 +1,200 @@
+use crate::rucksack::{ItemSet, Rucksack};
+
+fn main() {
+    let mut input = String::new();
+    let mut buffer = String::new();
+    loop {
+        buffer.clear();
+        match std::io::stdin().read_line(&mut buffer) {
+            Ok(0) => {
+                break;
+            }
+            Ok(_) => {
+                buffer.chars().for_each(|c| input.push(c));
+            }
+            Err(e) => panic!("{}", e),
+        }
+    }
+    let rucksacks: Vec<Rucksack> = input
+        .lines()
+        .enumerate()
+        .map(|(i, line)| match line.parse() {
+            Ok(v) => v,
+            Err(e) => panic!("Line {}: {}", i + 1, e),
+        })
+        .collect();
+
+    let ans1: u32 = rucksacks
+        .iter()
+        .enumerate()
+        .map(|(i, r)| {
+            let misplaced = r.misplaced();
+            match misplaced.len() {
+                1 => misplaced.priority_sum(),
+                n => panic!(
+                    "Line {}: {} item types in both compartments instead of 1",
+                    i + 1,
+                    n
+                ),
+            }
+        })
+        .sum();
+    println!("Part One: {}", ans1);
+
+    let groups = rucksacks.chunks_exact(3);
+    if !groups.remainder().is_empty() {
+        panic!(
+            "{} rucksacks cannot be split into groups of 3",
+            rucksacks.len()
+        );
+    }
+    let ans2: u32 = groups
+        .enumerate()
+        .map(|(i, group)| {
+            let badge = group
+                .iter()
+                .fold(ItemSet::all(), |acc, r| acc.intersection(r.items()));
+            match badge.len() {
+                1 => badge.priority_sum(),
+                n => panic!("Group {} shares {} item types instead of 1", i + 1, n),
+            }
+        })
+        .sum();
+    println!("Part Two: {}", ans2);
+}
+
+mod rucksack {
+    use std::{fmt, str::FromStr};
+
+    #[derive(Debug, PartialEq, Eq)]
+    pub enum ParseError {
+        Empty,
+        OddLength(usize),
+        InvalidItem(char),
+    }
+    impl fmt::Display for ParseError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                ParseError::Empty => write!(f, "Empty rucksack"),
+                ParseError::OddLength(n) => write!(f, "Odd number of items ({})", n),
+                ParseError::InvalidItem(c) => write!(f, "Invalid item {:?}", c),
+            }
+        }
+    }
+
+    // Bit `p - 1` is set when the item type with priority `p` is present.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct ItemSet(u64);
+    impl ItemSet {
+        pub fn all() -> Self {
+            Self((1 << 52) - 1)
+        }
+        pub fn intersection(&self, other: Self) -> Self {
+            Self(self.0 & other.0)
+        }
+        pub fn len(&self) -> u32 {
+            self.0.count_ones()
+        }
+        pub fn priority_sum(&self) -> u32 {
+            (0..52)
+                .filter(|i| self.0 >> i & 1 == 1)
+                .map(|i| i + 1)
+                .sum()
+        }
+    }
+    impl FromStr for ItemSet {
+        type Err = ParseError;
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let mut result = 0;
+            for c in s.chars() {
+                result |= 1 << (priority(c).ok_or(ParseError::InvalidItem(c))? - 1);
+            }
+            Ok(Self(result))
+        }
+    }
+
+    pub fn priority(c: char) -> Option<u32> {
+        match c {
+            'a'..='z' => Some(c as u32 - 'a' as u32 + 1),
+            'A'..='Z' => Some(c as u32 - 'A' as u32 + 27),
+            _ => None,
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Rucksack {
+        left: ItemSet,
+        right: ItemSet,
+    }
+    impl Rucksack {
+        pub fn items(&self) -> ItemSet {
+            ItemSet(self.left.0 | self.right.0)
+        }
+        pub fn misplaced(&self) -> ItemSet {
+            self.left.intersection(self.right)
+        }
+    }
+    impl FromStr for Rucksack {
+        type Err = ParseError;
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            if s.is_empty() {
+                return Err(ParseError::Empty);
+            }
+            if s.len() % 2 == 1 {
+                return Err(ParseError::OddLength(s.len()));
+            }
+            if let Some(c) = s.chars().find(|&c| priority(c).is_none()) {
+                return Err(ParseError::InvalidItem(c));
+            }
+            let (left, right) = s.split_at(s.len() / 2);
+            Ok(Self {
+                left: left.parse()?,
+                right: right.parse()?,
+            })
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_priority() {
+            assert_eq!(priority('a'), Some(1));
+            assert_eq!(priority('z'), Some(26));
+            assert_eq!(priority('A'), Some(27));
+            assert_eq!(priority('Z'), Some(52));
+            assert_eq!(priority('1'), None);
+        }
+        #[test]
+        fn test_rucksack() {
+            let rucksack: Rucksack = "vJrwpWtwJgWrhcsFMMfFFhFp".parse().unwrap();
+            assert_eq!(rucksack.misplaced(), "p".parse().unwrap());
+            assert_eq!(rucksack.misplaced().priority_sum(), 16);
+            let rucksack: Rucksack = "abAabA".parse().unwrap();
+            assert_eq!(rucksack.misplaced().len(), 3);
+            assert_eq!("abc".parse::<Rucksack>(), Err(ParseError::OddLength(3)));
+            assert_eq!("".parse::<Rucksack>(), Err(ParseError::Empty));
+            assert_eq!(
+                "ab-d".parse::<Rucksack>(),
+                Err(ParseError::InvalidItem('-'))
+            );
+        }
+        #[test]
+        fn test_sample() {
+            let input = include_str!("../../input/03-sample.txt");
+            let rucksacks: Vec<Rucksack> = input.lines().map(|l| l.parse().unwrap()).collect();
+            let misplaced: u32 = rucksacks.iter().map(|r| r.misplaced().priority_sum()).sum();
+            assert_eq!(misplaced, 157);
+            let badges: Vec<_> = rucksacks
+                .chunks(3)
+                .map(|g| {
+                    g.iter()
+                        .fold(ItemSet::all(), |acc, r| acc.intersection(r.items()))
+                })
+                .collect();
+            assert_eq!(badges, vec!["r".parse().unwrap(), "Z".parse().unwrap()]);
+            assert_eq!(badges.iter().map(|b| b.len()).sum::<u32>(), 2);
+        }
+    }
+}
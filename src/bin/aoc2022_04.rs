@@ -0,0 +1,95 @@
+use aoc2022::range::InclusiveRange;
+
+type Assignment = (InclusiveRange<u32>, InclusiveRange<u32>);
+
+fn main() {
+    let mut input = String::new();
+    let mut buffer = String::new();
+    loop {
+        buffer.clear();
+        match std::io::stdin().read_line(&mut buffer) {
+            Ok(0) => {
+                break;
+            }
+            Ok(_) => {
+                buffer.chars().for_each(|c| input.push(c));
+            }
+            Err(e) => panic!("{}", e),
+        }
+    }
+    let assignments = match parser::assignments(&input) {
+        Ok(v) => v,
+        Err(line) => panic!("Line {}: invalid assignment", line),
+    };
+
+    let ans1 = assignments
+        .iter()
+        .filter(|(a, b)| a.contains(b) || b.contains(a))
+        .count();
+    println!("Part One: {}", ans1);
+    let ans2 = assignments.iter().filter(|(a, b)| a.overlaps(b)).count();
+    println!("Part Two: {}", ans2);
+}
+
+mod parser {
+    use super::Assignment;
+    use aoc2022::range::InclusiveRange;
+    use nom::{
+        bytes::complete::tag,
+        character::complete::u32 as nom_u32,
+        combinator::{all_consuming, map_opt},
+        sequence::separated_pair,
+        IResult,
+    };
+
+    fn range(input: &str) -> IResult<&str, InclusiveRange<u32>> {
+        map_opt(
+            separated_pair(nom_u32, tag("-"), nom_u32),
+            |(start, end)| InclusiveRange::new(start, end),
+        )(input)
+    }
+    fn assignment(input: &str) -> IResult<&str, Assignment> {
+        separated_pair(range, tag(","), range)(input)
+    }
+    // Fails with the 1-based number of the first line that is not an assignment.
+    pub fn assignments(input: &str) -> Result<Vec<Assignment>, usize> {
+        input
+            .lines()
+            .enumerate()
+            .map(|(i, line)| match all_consuming(assignment)(line) {
+                Ok((_, v)) => Ok(v),
+                Err(_) => Err(i + 1),
+            })
+            .collect()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_range() {
+            assert_eq!(range("2-4"), Ok(("", InclusiveRange::new(2, 4).unwrap())));
+            assert!(range("4-2").is_err());
+        }
+        #[test]
+        fn test_assignments() {
+            let input = include_str!("../../input/04-sample.txt");
+            let assignments = assignments(input).unwrap();
+            assert_eq!(assignments.len(), 6);
+            assert_eq!(
+                assignments[3],
+                (
+                    InclusiveRange::new(2, 8).unwrap(),
+                    InclusiveRange::new(3, 7).unwrap()
+                )
+            );
+        }
+        #[test]
+        fn test_invalid_line() {
+            assert_eq!(assignments("2-4,6-8\n5-3,1-2\n2-8,3-7\n"), Err(2));
+            assert_eq!(assignments("2-4,6-8\n\n2-8,3-7\n"), Err(2));
+            assert_eq!(assignments("2-4,6-8\n2-8,3-7x\n"), Err(2));
+        }
+    }
+}
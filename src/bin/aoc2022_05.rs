@@ -0,0 +1,304 @@
+use crate::crane::{Crane, CrateMover9000, CrateMover9001};
+
+fn main() {
+    let mut input = String::new();
+    let mut buffer = String::new();
+    loop {
+        buffer.clear();
+        match std::io::stdin().read_line(&mut buffer) {
+            Ok(0) => {
+                break;
+            }
+            Ok(_) => {
+                buffer.chars().for_each(|c| input.push(c));
+            }
+            Err(e) => panic!("{}", e),
+        }
+    }
+    let (stacks, moves) = parser::procedure(&input).unwrap().1;
+
+    let run = |crane: &dyn Crane| {
+        let mut stacks = stacks.clone();
+        for (i, m) in moves.iter().enumerate() {
+            if let Err(e) = stacks.apply(crane, m) {
+                panic!("Move {}: {}", i + 1, e);
+            }
+        }
+        stacks.tops()
+    };
+    println!("Part One: {}", run(&CrateMover9000));
+    println!("Part Two: {}", run(&CrateMover9001));
+}
+
+mod crane {
+    use std::fmt;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Move {
+        pub count: usize,
+        pub from: usize,
+        pub to: usize,
+    }
+
+    #[derive(Debug, PartialEq, Eq)]
+    pub enum MoveError {
+        NoSuchStack(usize),
+        NotEnoughCrates {
+            stack: usize,
+            wanted: usize,
+            available: usize,
+        },
+    }
+    impl fmt::Display for MoveError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                MoveError::NoSuchStack(stack) => write!(f, "No stack {}", stack),
+                MoveError::NotEnoughCrates {
+                    stack,
+                    wanted,
+                    available: 0,
+                } => write!(f, "Cannot move {} from empty stack {}", wanted, stack),
+                MoveError::NotEnoughCrates {
+                    stack,
+                    wanted,
+                    available,
+                } => write!(
+                    f,
+                    "Cannot move {} from stack {} holding {}",
+                    wanted, stack, available
+                ),
+            }
+        }
+    }
+
+    pub trait Crane {
+        fn transfer(&self, from: &mut Vec<char>, to: &mut Vec<char>, count: usize);
+    }
+
+    // Moves one crate at a time, so the moved crates end up reversed.
+    pub struct CrateMover9000;
+    impl Crane for CrateMover9000 {
+        fn transfer(&self, from: &mut Vec<char>, to: &mut Vec<char>, count: usize) {
+            for _ in 0..count {
+                to.push(from.pop().unwrap());
+            }
+        }
+    }
+
+    // Moves all crates at once, keeping their order.
+    pub struct CrateMover9001;
+    impl Crane for CrateMover9001 {
+        fn transfer(&self, from: &mut Vec<char>, to: &mut Vec<char>, count: usize) {
+            let lifted = from.split_off(from.len() - count);
+            to.extend(lifted);
+        }
+    }
+
+    // Stacks are numbered from 1 as in the drawing; each is listed bottom to top.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct Stacks(pub Vec<Vec<char>>);
+    impl Stacks {
+        pub fn apply(&mut self, crane: &dyn Crane, m: &Move) -> Result<(), MoveError> {
+            let n = self.0.len();
+            for stack in [m.from, m.to] {
+                if !(1..=n).contains(&stack) {
+                    return Err(MoveError::NoSuchStack(stack));
+                }
+            }
+            let available = self.0[m.from - 1].len();
+            if available < m.count {
+                return Err(MoveError::NotEnoughCrates {
+                    stack: m.from,
+                    wanted: m.count,
+                    available,
+                });
+            }
+            if m.from == m.to {
+                return Ok(());
+            }
+            let (from, to) = if m.from < m.to {
+                let (left, right) = self.0.split_at_mut(m.to - 1);
+                (&mut left[m.from - 1], &mut right[0])
+            } else {
+                let (left, right) = self.0.split_at_mut(m.from - 1);
+                (&mut right[0], &mut left[m.to - 1])
+            };
+            crane.transfer(from, to, m.count);
+            Ok(())
+        }
+        pub fn tops(&self) -> String {
+            self.0.iter().filter_map(|s| s.last()).collect()
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn sample() -> Stacks {
+            Stacks(vec![vec!['Z', 'N'], vec!['M', 'C', 'D'], vec!['P']])
+        }
+        fn mv(count: usize, from: usize, to: usize) -> Move {
+            Move { count, from, to }
+        }
+
+        #[test]
+        fn crate_mover_9000_reverses() {
+            let mut stacks = sample();
+            stacks.apply(&CrateMover9000, &mv(2, 2, 1)).unwrap();
+            assert_eq!(stacks.0[0], vec!['Z', 'N', 'D', 'C']);
+            assert_eq!(stacks.0[1], vec!['M']);
+        }
+        #[test]
+        fn crate_mover_9001_keeps_order() {
+            let mut stacks = sample();
+            stacks.apply(&CrateMover9001, &mv(2, 2, 1)).unwrap();
+            assert_eq!(stacks.0[0], vec!['Z', 'N', 'C', 'D']);
+            stacks.apply(&CrateMover9001, &mv(3, 1, 3)).unwrap();
+            assert_eq!(stacks.0[2], vec!['P', 'N', 'C', 'D']);
+            assert_eq!(stacks.tops(), "ZMD");
+        }
+        #[test]
+        fn invalid_moves() {
+            let mut stacks = sample();
+            assert_eq!(
+                stacks.apply(&CrateMover9000, &mv(1, 4, 1)),
+                Err(MoveError::NoSuchStack(4))
+            );
+            assert_eq!(
+                stacks.apply(&CrateMover9000, &mv(1, 1, 0)),
+                Err(MoveError::NoSuchStack(0))
+            );
+            stacks.apply(&CrateMover9000, &mv(1, 3, 1)).unwrap();
+            assert_eq!(
+                stacks.apply(&CrateMover9001, &mv(1, 3, 2)),
+                Err(MoveError::NotEnoughCrates {
+                    stack: 3,
+                    wanted: 1,
+                    available: 0
+                })
+            );
+            assert_eq!(
+                stacks
+                    .apply(&CrateMover9001, &mv(1, 3, 2))
+                    .unwrap_err()
+                    .to_string(),
+                "Cannot move 1 from empty stack 3"
+            );
+            assert_eq!(stacks.0[0], vec!['Z', 'N', 'P']);
+        }
+    }
+}
+
+mod parser {
+    use super::crane::{Move, Stacks};
+    use nom::{
+        branch::alt,
+        bytes::complete::tag,
+        character::complete::{anychar, digit1, space0, space1},
+        combinator::{map, map_res, value},
+        multi::{separated_list0, separated_list1},
+        sequence::{delimited, preceded, separated_pair, tuple},
+        IResult,
+    };
+
+    fn num(input: &str) -> IResult<&str, usize> {
+        map_res(digit1, |s: &str| s.parse::<usize>())(input)
+    }
+    fn slot(input: &str) -> IResult<&str, Option<char>> {
+        alt((
+            map(delimited(tag("["), anychar, tag("]")), Some),
+            value(None, tag("   ")),
+        ))(input)
+    }
+    fn row(input: &str) -> IResult<&str, Vec<Option<char>>> {
+        separated_list1(tag(" "), slot)(input)
+    }
+    fn labels(input: &str) -> IResult<&str, Vec<usize>> {
+        delimited(space0, separated_list1(space1, num), space0)(input)
+    }
+    fn drawing(input: &str) -> IResult<&str, Stacks> {
+        map(
+            separated_pair(separated_list0(tag("\n"), row), tag("\n"), labels),
+            |(rows, labels)| {
+                let mut stacks = vec![vec![]; labels.len()];
+                for row in rows.iter().rev() {
+                    for (stack, slot) in stacks.iter_mut().zip(row) {
+                        if let Some(c) = slot {
+                            stack.push(*c);
+                        }
+                    }
+                }
+                Stacks(stacks)
+            },
+        )(input)
+    }
+    fn step(input: &str) -> IResult<&str, Move> {
+        map(
+            tuple((
+                preceded(tag("move "), num),
+                preceded(tag(" from "), num),
+                preceded(tag(" to "), num),
+            )),
+            |(count, from, to)| Move { count, from, to },
+        )(input)
+    }
+    pub fn procedure(input: &str) -> IResult<&str, (Stacks, Vec<Move>)> {
+        separated_pair(drawing, tag("\n\n"), separated_list0(tag("\n"), step))(input)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_row() {
+            assert_eq!(row("    [D]    "), Ok(("", vec![None, Some('D'), None])));
+            assert_eq!(
+                row("[Z] [M] [P]"),
+                Ok(("", vec![Some('Z'), Some('M'), Some('P')]))
+            );
+        }
+        #[test]
+        fn test_labels() {
+            assert_eq!(labels(" 1   2   3 "), Ok(("", vec![1, 2, 3])));
+            assert_eq!(labels(" 1   2   3\n"), Ok(("\n", vec![1, 2, 3])));
+        }
+        #[test]
+        fn test_step() {
+            assert_eq!(
+                step("move 3 from 1 to 3"),
+                Ok((
+                    "",
+                    Move {
+                        count: 3,
+                        from: 1,
+                        to: 3
+                    }
+                ))
+            );
+        }
+        #[test]
+        fn test_procedure() {
+            let input = include_str!("../../input/05-sample.txt");
+            let (rest, (stacks, moves)) = procedure(input).unwrap();
+            assert_eq!(rest, "\n");
+            assert_eq!(
+                stacks,
+                Stacks(vec![vec!['Z', 'N'], vec!['M', 'C', 'D'], vec!['P']])
+            );
+            assert_eq!(moves.len(), 4);
+        }
+        #[test]
+        fn test_trimmed_drawing() {
+            let (_, (stacks, _)) =
+                procedure("    [D]\n[N] [C]\n 1   2   3 \n\nmove 1 from 2 to 1").unwrap();
+            assert_eq!(stacks, Stacks(vec![vec!['N'], vec!['C', 'D'], vec![]]));
+
+            let (_, (stacks, moves)) =
+                procedure("    [D]\n[N] [C]\n 1   2   3\n\nmove 1 from 2 to 1").unwrap();
+            assert_eq!(stacks, Stacks(vec![vec!['N'], vec!['C', 'D'], vec![]]));
+            assert_eq!(moves.len(), 1);
+        }
+    }
+}
@@ -1,79 +1,60 @@
+use crate::{height_map::HeightMap, route::RouteFinder};
 use std::collections::VecDeque;
 
-const INF: usize = 1 << 60;
-
 fn main() {
-    let mut input = vec![];
+    let mut input = String::new();
     let mut buffer = String::new();
     loop {
         buffer.clear();
         match std::io::stdin().read_line(&mut buffer) {
             Ok(0) => break,
-            Ok(_) => input.push(buffer.trim().to_string()),
+            Ok(_) => input.push_str(&buffer),
             Err(e) => panic!("{}", e),
         }
     }
-    let mut field: Vec<Vec<_>> = input
-        .into_iter()
-        .map(|line| line.bytes().collect())
-        .collect();
-    let mut start_candidates = vec![];
-    let mut si = 0;
-    let mut sj = 0;
-    let mut ei = 0;
-    let mut ej = 0;
-    for (i, row) in field.iter().enumerate() {
-        for (j, value) in row.iter().enumerate() {
-            match value {
-                b'S' => {
-                    si = i;
-                    sj = j;
-                    start_candidates.push((i, j));
-                }
-                b'a' => {
-                    start_candidates.push((i, j));
-                }
-                b'E' => {
-                    ei = i;
-                    ej = j;
-                }
-                _ => (),
-            }
-        }
-    }
-    field[si][sj] = b'a';
-    field[ei][ej] = b'z';
+    let height_map: HeightMap = match input.parse() {
+        Ok(v) => v,
+        Err(e) => panic!("{}", e),
+    };
+    let mut route_finder = RouteFinder::new(&height_map);
 
-    let ans1 = bfs(&field, si, sj, ei, ej);
-    println!("Part One: {}", ans1);
-    let ans2 = start_candidates
-        .iter()
-        .map(|&(si, sj)| bfs(&field, si, sj, ei, ej))
-        .min()
-        .unwrap();
-    println!("Part Two: {}", ans2);
-}
+    let ans1 = route_finder.distance(height_map.start(), height_map.end());
+    println!("Part One: {}", ans1.unwrap());
+    let queries: Vec<_> = height_map
+        .positions_at_height(b'a')
+        .map(|from| (from, height_map.end()))
+        .collect();
+    let ans2 = route_finder.distances(&queries).into_iter().flatten().min();
+    println!("Part Two: {}", ans2.unwrap());
 
-fn bfs(field: &[Vec<u8>], si: usize, sj: usize, ei: usize, ej: usize) -> usize {
-    let rows = field.len();
-    let cols = field[0].len();
-    let mut cost = vec![vec![INF; cols]; rows];
-    cost[si][sj] = 0;
-    let mut q = VecDeque::new();
-    q.push_back((si, sj));
-    while let Some((i, j)) = q.pop_front() {
-        for (ni, nj) in adjacent_grids_4(i, j, rows, cols) {
-            if field[ni][nj] > field[i][j] + 1 {
-                continue;
+    let args: Vec<_> = std::env::args().skip(1).collect();
+    if let Some(query_path) = args.iter().find(|a| !a.starts_with("--")) {
+        let query_input = match std::fs::read_to_string(query_path) {
+            Ok(v) => v,
+            Err(e) => panic!("{}: {}", query_path, e),
+        };
+        let queries = match parser::queries(&query_input) {
+            Ok(v) => v,
+            Err(line) => panic!("{}: invalid query on line {}", query_path, line),
+        };
+        if args.iter().any(|a| a == "--paths") {
+            let paths = route_finder.paths(&queries);
+            for ((from, to), path) in queries.iter().zip(paths) {
+                match path {
+                    Some(p) => println!("{:?} -> {:?}: {:?}", from, to, p),
+                    None => println!("{:?} -> {:?}: unreachable", from, to),
+                }
             }
-            let nc = cost[i][j] + 1;
-            if cost[ni][nj] > nc {
-                cost[ni][nj] = nc;
-                q.push_back((ni, nj));
+        } else {
+            let distances = route_finder.distances(&queries);
+            for ((from, to), distance) in queries.iter().zip(distances) {
+                match distance {
+                    Some(d) => println!("{:?} -> {:?}: {}", from, to, d),
+                    None => println!("{:?} -> {:?}: unreachable", from, to),
+                }
             }
         }
     }
-    cost[ei][ej]
 }
 
 fn adjacent_grids(
@@ -102,3 +83,447 @@ fn adjacent_grids_4(
 ) -> impl Iterator<Item = (usize, usize)> {
     adjacent_grids(i, j, height, width, &[(0, 1), (1, 0), (0, !0), (!0, 0)])
 }
+
+mod route {
+    use super::{adjacent_grids_4, height_map::HeightMap, VecDeque};
+    use std::collections::{HashMap, HashSet};
+
+    pub type Position = (usize, usize);
+    pub type Query = (Position, Position);
+
+    struct BfsTree {
+        cost: Vec<Vec<Option<usize>>>,
+        parent: Vec<Vec<Option<Position>>>,
+    }
+    impl BfsTree {
+        fn build(height_map: &HeightMap, root: Position, reverse: bool) -> Self {
+            let rows = height_map.rows();
+            let cols = height_map.cols();
+            let mut cost = vec![vec![None; cols]; rows];
+            let mut parent = vec![vec![None; cols]; rows];
+            cost[root.0][root.1] = Some(0);
+            let mut q = VecDeque::new();
+            q.push_back(root);
+            while let Some((i, j)) = q.pop_front() {
+                let c = cost[i][j].unwrap();
+                for (ni, nj) in adjacent_grids_4(i, j, rows, cols) {
+                    let (lower, upper) = if reverse {
+                        (height_map.height(ni, nj), height_map.height(i, j))
+                    } else {
+                        (height_map.height(i, j), height_map.height(ni, nj))
+                    };
+                    if upper > lower + 1 || cost[ni][nj].is_some() {
+                        continue;
+                    }
+                    cost[ni][nj] = Some(c + 1);
+                    parent[ni][nj] = Some((i, j));
+                    q.push_back((ni, nj));
+                }
+            }
+            Self { cost, parent }
+        }
+        fn cost(&self, at: Position) -> Option<usize> {
+            self.cost[at.0][at.1]
+        }
+        fn walk_to_root(&self, at: Position) -> Option<Vec<Position>> {
+            self.cost(at)?;
+            let mut result = vec![at];
+            let mut current = at;
+            while let Some(p) = self.parent[current.0][current.1] {
+                result.push(p);
+                current = p;
+            }
+            Some(result)
+        }
+    }
+
+    pub struct RouteFinder<'a> {
+        height_map: &'a HeightMap,
+        trees_from: HashMap<Position, BfsTree>,
+        trees_to: HashMap<Position, BfsTree>,
+    }
+    impl<'a> RouteFinder<'a> {
+        pub fn new(height_map: &'a HeightMap) -> Self {
+            Self {
+                height_map,
+                trees_from: HashMap::new(),
+                trees_to: HashMap::new(),
+            }
+        }
+        fn prepare_from(&mut self, from: Position) {
+            let height_map = self.height_map;
+            self.trees_from
+                .entry(from)
+                .or_insert_with(|| BfsTree::build(height_map, from, false));
+        }
+        fn prepare_to(&mut self, to: Position) {
+            let height_map = self.height_map;
+            self.trees_to
+                .entry(to)
+                .or_insert_with(|| BfsTree::build(height_map, to, true));
+        }
+        fn is_cached(&self, from: Position, to: Position) -> bool {
+            self.trees_from.contains_key(&from) || self.trees_to.contains_key(&to)
+        }
+        fn in_bounds(&self, at: Position) -> bool {
+            at.0 < self.height_map.rows() && at.1 < self.height_map.cols()
+        }
+        pub fn distance(&mut self, from: Position, to: Position) -> Option<usize> {
+            if !self.in_bounds(from) || !self.in_bounds(to) {
+                return None;
+            }
+            if !self.is_cached(from, to) {
+                self.prepare_from(from);
+            }
+            match self.trees_from.get(&from) {
+                Some(tree) => tree.cost(to),
+                None => self.trees_to[&to].cost(from),
+            }
+        }
+        pub fn path(&mut self, from: Position, to: Position) -> Option<Vec<Position>> {
+            if !self.in_bounds(from) || !self.in_bounds(to) {
+                return None;
+            }
+            if !self.is_cached(from, to) {
+                self.prepare_from(from);
+            }
+            match self.trees_from.get(&from) {
+                Some(tree) => tree.walk_to_root(to).map(|mut path| {
+                    path.reverse();
+                    path
+                }),
+                None => self.trees_to[&to].walk_to_root(from),
+            }
+        }
+        // One tree per distinct source or per distinct target, whichever is fewer.
+        fn prepare(&mut self, queries: &[Query]) {
+            let queries: Vec<_> = queries
+                .iter()
+                .filter(|&&(from, to)| {
+                    self.in_bounds(from) && self.in_bounds(to) && !self.is_cached(from, to)
+                })
+                .collect();
+            let sources: HashSet<_> = queries.iter().map(|q| q.0).collect();
+            let targets: HashSet<_> = queries.iter().map(|q| q.1).collect();
+            if sources.len() <= targets.len() {
+                sources.into_iter().for_each(|from| self.prepare_from(from));
+            } else {
+                targets.into_iter().for_each(|to| self.prepare_to(to));
+            }
+        }
+        pub fn distances(&mut self, queries: &[Query]) -> Vec<Option<usize>> {
+            self.prepare(queries);
+            queries
+                .iter()
+                .map(|&(from, to)| self.distance(from, to))
+                .collect()
+        }
+        pub fn paths(&mut self, queries: &[Query]) -> Vec<Option<Vec<Position>>> {
+            self.prepare(queries);
+            queries
+                .iter()
+                .map(|&(from, to)| self.path(from, to))
+                .collect()
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        const SAMPLE: &str = "Sabqponm\nabcryxxl\naccszExk\nacctuvwj\nabdefghi\n";
+
+        #[test]
+        fn distance_matches_sample() {
+            let height_map: HeightMap = SAMPLE.parse().unwrap();
+            let mut route_finder = RouteFinder::new(&height_map);
+            assert_eq!(
+                route_finder.distance(height_map.start(), height_map.end()),
+                Some(31)
+            );
+            assert_eq!(route_finder.distance((0, 0), (0, 0)), Some(0));
+            assert_eq!(route_finder.distance((9, 9), (0, 0)), None);
+        }
+        #[test]
+        fn distances_share_reverse_tree() {
+            let height_map: HeightMap = SAMPLE.parse().unwrap();
+            let mut route_finder = RouteFinder::new(&height_map);
+            let queries: Vec<_> = height_map
+                .positions_at_height(b'a')
+                .map(|from| (from, height_map.end()))
+                .collect();
+            let distances = route_finder.distances(&queries);
+            assert_eq!(distances.into_iter().flatten().min(), Some(29));
+            assert!(route_finder.trees_from.is_empty());
+            assert_eq!(route_finder.trees_to.len(), 1);
+        }
+        #[test]
+        fn path_is_consistent_with_distance() {
+            let height_map: HeightMap = SAMPLE.parse().unwrap();
+            let mut route_finder = RouteFinder::new(&height_map);
+            let queries = [((0, 0), height_map.end()), ((4, 0), height_map.end())];
+            for (path, distance) in route_finder
+                .paths(&queries)
+                .into_iter()
+                .zip(route_finder.distances(&queries))
+            {
+                let path = path.unwrap();
+                assert_eq!(path.len(), distance.unwrap() + 1);
+                assert_eq!(path.last(), Some(&height_map.end()));
+            }
+            assert_eq!(
+                route_finder.path(height_map.end(), (0, 0)).unwrap()[0],
+                height_map.end()
+            );
+            let distance = route_finder.distance(height_map.end(), (0, 0)).unwrap();
+            assert_eq!(
+                route_finder.path(height_map.end(), (0, 0)).unwrap().len(),
+                distance + 1
+            );
+        }
+    }
+}
+
+mod parser {
+    use super::route::{Position, Query};
+    use nom::{
+        bytes::complete::tag,
+        character::complete::{digit1, space1},
+        combinator::{all_consuming, map, map_res},
+        sequence::{separated_pair, tuple},
+        IResult,
+    };
+
+    fn num(input: &str) -> IResult<&str, usize> {
+        map_res(digit1, |s: &str| s.parse::<usize>())(input)
+    }
+    fn position(input: &str) -> IResult<&str, Position> {
+        separated_pair(num, tag(","), num)(input)
+    }
+    fn query(input: &str) -> IResult<&str, Query> {
+        map(tuple((position, space1, position)), |(from, _, to)| {
+            (from, to)
+        })(input)
+    }
+    // Fails with the 1-based number of the first line that is not a query.
+    pub fn queries(input: &str) -> Result<Vec<Query>, usize> {
+        input
+            .lines()
+            .enumerate()
+            .map(|(i, line)| match all_consuming(query)(line.trim_end()) {
+                Ok((_, q)) => Ok(q),
+                Err(_) => Err(i + 1),
+            })
+            .collect()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_query() {
+            assert_eq!(query("0,1 2,3"), Ok(("", ((0, 1), (2, 3)))));
+        }
+        #[test]
+        fn test_queries() {
+            assert_eq!(
+                queries("0,1 2,3\n4,5 6,7\n"),
+                Ok(vec![((0, 1), (2, 3)), ((4, 5), (6, 7))])
+            );
+            assert_eq!(queries("0,0 2,5\n0,0 x\n4,0 2,5"), Err(2));
+            assert_eq!(queries("0,0 2,5\n\n4,0 2,5\n"), Err(2));
+            assert_eq!(queries("0,0 2,5 7\n"), Err(1));
+        }
+    }
+}
+
+mod height_map {
+    use std::{fmt, str::FromStr};
+
+    #[derive(Debug, PartialEq, Eq)]
+    pub enum ParseError {
+        Empty,
+        MissingStart,
+        MissingEnd,
+        DuplicateStart((usize, usize), (usize, usize)),
+        DuplicateEnd((usize, usize), (usize, usize)),
+        RaggedRow {
+            row: usize,
+            expected: usize,
+            found: usize,
+        },
+        InvalidChar {
+            at: (usize, usize),
+            found: char,
+        },
+    }
+    impl fmt::Display for ParseError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                ParseError::Empty => write!(f, "Empty heightmap"),
+                ParseError::MissingStart => write!(f, "Missing start `S`"),
+                ParseError::MissingEnd => write!(f, "Missing end `E`"),
+                ParseError::DuplicateStart(a, b) => {
+                    write!(f, "Duplicate start `S` at {:?} and {:?}", a, b)
+                }
+                ParseError::DuplicateEnd(a, b) => {
+                    write!(f, "Duplicate end `E` at {:?} and {:?}", a, b)
+                }
+                ParseError::RaggedRow {
+                    row,
+                    expected,
+                    found,
+                } => write!(
+                    f,
+                    "Row {} has {} columns, expected {}",
+                    row, found, expected
+                ),
+                ParseError::InvalidChar { at, found } => {
+                    write!(f, "Invalid character {:?} at {:?}", found, at)
+                }
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct HeightMap {
+        heights: Vec<Vec<u8>>,
+        start: (usize, usize),
+        end: (usize, usize),
+    }
+    impl HeightMap {
+        pub fn rows(&self) -> usize {
+            self.heights.len()
+        }
+        pub fn cols(&self) -> usize {
+            self.heights[0].len()
+        }
+        pub fn height(&self, i: usize, j: usize) -> u8 {
+            self.heights[i][j]
+        }
+        pub fn start(&self) -> (usize, usize) {
+            self.start
+        }
+        pub fn end(&self) -> (usize, usize) {
+            self.end
+        }
+        pub fn positions_at_height(&self, height: u8) -> impl Iterator<Item = (usize, usize)> + '_ {
+            self.heights.iter().enumerate().flat_map(move |(i, row)| {
+                row.iter()
+                    .enumerate()
+                    .filter(move |(_j, &h)| h == height)
+                    .map(move |(j, _h)| (i, j))
+            })
+        }
+    }
+    impl FromStr for HeightMap {
+        type Err = ParseError;
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let lines: Vec<_> = s.trim_end_matches(['\n', '\r']).lines().collect();
+            if lines.is_empty() || lines[0].is_empty() {
+                return Err(ParseError::Empty);
+            }
+            let cols = lines[0].chars().count();
+            let mut heights = vec![];
+            let mut start = None;
+            let mut end = None;
+            for (i, line) in lines.iter().enumerate() {
+                let mut row = vec![];
+                for (j, c) in line.chars().enumerate() {
+                    let h = match c {
+                        'S' => {
+                            if let Some(prev) = start {
+                                return Err(ParseError::DuplicateStart(prev, (i, j)));
+                            }
+                            start = Some((i, j));
+                            b'a'
+                        }
+                        'E' => {
+                            if let Some(prev) = end {
+                                return Err(ParseError::DuplicateEnd(prev, (i, j)));
+                            }
+                            end = Some((i, j));
+                            b'z'
+                        }
+                        'a'..='z' => c as u8,
+                        _ => {
+                            return Err(ParseError::InvalidChar {
+                                at: (i, j),
+                                found: c,
+                            })
+                        }
+                    };
+                    row.push(h);
+                }
+                if row.len() != cols {
+                    return Err(ParseError::RaggedRow {
+                        row: i,
+                        expected: cols,
+                        found: row.len(),
+                    });
+                }
+                heights.push(row);
+            }
+            Ok(Self {
+                heights,
+                start: start.ok_or(ParseError::MissingStart)?,
+                end: end.ok_or(ParseError::MissingEnd)?,
+            })
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn parse_height_map() {
+            let height_map: HeightMap = "Sab\nczE\n".parse().unwrap();
+            assert_eq!(height_map.rows(), 2);
+            assert_eq!(height_map.cols(), 3);
+            assert_eq!(height_map.start(), (0, 0));
+            assert_eq!(height_map.end(), (1, 2));
+            assert_eq!(height_map.height(0, 0), b'a');
+            assert_eq!(height_map.height(1, 2), b'z');
+        }
+        #[test]
+        fn reject_missing_start_or_end() {
+            assert_eq!("abE".parse::<HeightMap>(), Err(ParseError::MissingStart));
+            assert_eq!("Sab".parse::<HeightMap>(), Err(ParseError::MissingEnd));
+            assert_eq!("".parse::<HeightMap>(), Err(ParseError::Empty));
+        }
+        #[test]
+        fn reject_duplicate_start_or_end() {
+            assert_eq!(
+                "SaE\naSb".parse::<HeightMap>(),
+                Err(ParseError::DuplicateStart((0, 0), (1, 1)))
+            );
+            assert_eq!(
+                "SEE".parse::<HeightMap>(),
+                Err(ParseError::DuplicateEnd((0, 1), (0, 2)))
+            );
+        }
+        #[test]
+        fn reject_ragged_rows() {
+            assert_eq!(
+                "SaE\nab".parse::<HeightMap>(),
+                Err(ParseError::RaggedRow {
+                    row: 1,
+                    expected: 3,
+                    found: 2
+                })
+            );
+        }
+        #[test]
+        fn reject_invalid_chars() {
+            assert_eq!(
+                "SaE\na1b".parse::<HeightMap>(),
+                Err(ParseError::InvalidChar {
+                    at: (1, 1),
+                    found: '1'
+                })
+            );
+        }
+    }
+}
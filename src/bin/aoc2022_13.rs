@@ -1,33 +1,150 @@
-use std::{cmp::Ordering, collections::BTreeSet};
+use crate::atom::Atom;
+use num_bigint::BigInt;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use std::{cmp::Ordering, fmt, str::FromStr};
 
-#[derive(Debug, Clone)]
-pub enum Item {
-    V(usize),
-    L(Vec<Item>),
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Packet<A> {
+    V(A),
+    L(Vec<Packet<A>>),
 }
-impl Ord for Item {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+pub type Item = Packet<usize>;
+pub type PacketPair<A> = (Packet<A>, Packet<A>);
+impl<A: Atom> Packet<A> {
+    pub fn packet_cmp(&self, other: &Self) -> Ordering {
         match (self, other) {
-            (Item::V(a), Item::V(b)) => a.cmp(b),
-            (Item::L(a), Item::L(b)) => a.iter().cmp(b),
-            (Item::V(a), Item::L(_)) => Item::L(vec![Item::V(*a)]).cmp(other),
-            (Item::L(_), Item::V(_)) => other.cmp(self).reverse(),
+            (Packet::V(a), Packet::V(b)) => a.atom_cmp(b),
+            (Packet::L(a), Packet::L(b)) => a.iter().map(PacketOrd).cmp(b.iter().map(PacketOrd)),
+            (Packet::V(_), Packet::L(_)) => Packet::L(vec![self.clone()]).packet_cmp(other),
+            (Packet::L(_), Packet::V(_)) => other.packet_cmp(self).reverse(),
         }
     }
 }
-impl PartialOrd for Item {
+
+#[derive(Debug)]
+pub struct PacketOrd<'a, A>(pub &'a Packet<A>);
+impl<A: Atom> Ord for PacketOrd<'_, A> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.packet_cmp(other.0)
+    }
+}
+impl<A: Atom> PartialOrd for PacketOrd<'_, A> {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
     }
 }
-impl Eq for Item {}
-impl PartialEq for Item {
+impl<A: Atom> Eq for PacketOrd<'_, A> {}
+impl<A: Atom> PartialEq for PacketOrd<'_, A> {
     fn eq(&self, other: &Self) -> bool {
-        matches!(self.cmp(other), Ordering::Equal)
+        self.cmp(other).is_eq()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SortedPackets<A = usize> {
+    packets: Vec<Packet<A>>,
+}
+impl<A: Atom> SortedPackets<A> {
+    // Inserts after any packets that compare equal and returns the 1-based index used.
+    pub fn insert(&mut self, item: Packet<A>) -> usize {
+        let i = self
+            .packets
+            .partition_point(|p| p.packet_cmp(&item).is_le());
+        self.packets.insert(i, item);
+        i + 1
+    }
+    pub fn rank(&self, item: &Packet<A>) -> usize {
+        self.packets.partition_point(|p| p.packet_cmp(item).is_lt()) + 1
+    }
+    pub fn len(&self) -> usize {
+        self.packets.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.packets.is_empty()
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &Packet<A>> {
+        self.packets.iter()
+    }
+}
+impl<A: Atom> FromIterator<Packet<A>> for SortedPackets<A> {
+    fn from_iter<I: IntoIterator<Item = Packet<A>>>(iter: I) -> Self {
+        let mut packets: Vec<_> = iter.into_iter().collect();
+        packets.sort_by(Packet::packet_cmp);
+        Self { packets }
+    }
+}
+impl<A: Atom> fmt::Display for Packet<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Packet::V(v) => v.fmt_atom(f),
+            Packet::L(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
+impl<A: Atom> FromStr for Packet<A> {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match parser::item(s) {
+            Ok(("", item)) => Ok(item),
+            _ => Err("Invalid packet"),
+        }
+    }
+}
+impl From<&Item> for Value {
+    fn from(item: &Item) -> Self {
+        match item {
+            Item::V(v) => Value::from(*v),
+            Item::L(items) => Value::Array(items.iter().map(Value::from).collect()),
+        }
+    }
+}
+impl TryFrom<&Value> for Item {
+    type Error = &'static str;
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Number(n) => match n.as_u64().and_then(|v| usize::try_from(v).ok()) {
+                Some(v) => Ok(Item::V(v)),
+                None => Err("Packet values must be non-negative integers"),
+            },
+            Value::Array(values) => Ok(Item::L(
+                values
+                    .iter()
+                    .map(Item::try_from)
+                    .collect::<Result<_, _>>()?,
+            )),
+            _ => Err("Packets consist of integers and lists only"),
+        }
+    }
+}
+impl Serialize for Item {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Value::from(self).serialize(serializer)
+    }
+}
+impl<'de> Deserialize<'de> for Item {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        Item::try_from(&value).map_err(serde::de::Error::custom)
     }
 }
 
 fn main() {
+    let args: Vec<_> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("generate") {
+        generate_packets(&args);
+        return;
+    }
+
     let mut input = String::new();
     let mut buffer = String::new();
     loop {
@@ -43,24 +160,147 @@ fn main() {
         }
     }
 
-    let pairs = parser::item_pairs(&input).unwrap().1;
-    let ans1 = pairs
+    let atoms = args
+        .iter()
+        .find_map(|a| a.strip_prefix("--atoms="))
+        .unwrap_or("usize");
+    match atoms {
+        "usize" => solve::<usize>(&input, &args),
+        "i64" => solve::<i64>(&input, &args),
+        "bigint" => solve::<BigInt>(&input, &args),
+        "string" => solve::<String>(&input, &args),
+        _ => panic!("Unknown atom type: {}", atoms),
+    }
+}
+
+fn arg_value<T: FromStr>(args: &[String], name: &str, default: T) -> T {
+    match args.iter().find_map(|a| a.strip_prefix(name)) {
+        Some(v) => match v.parse() {
+            Ok(v) => v,
+            Err(_) => panic!("Invalid value for {}{}", name, v),
+        },
+        None => default,
+    }
+}
+
+fn generate_packets(args: &[String]) {
+    let depth = arg_value(args, "--depth=", 4);
+    if depth == 0 {
+        panic!("--depth= must be at least 1, every packet is a list");
+    }
+    let mut generator = generator::PacketGenerator::new(
+        arg_value(args, "--seed=", 2022),
+        depth,
+        arg_value(args, "--width=", 5),
+    )
+    .with_max_value(arg_value(args, "--max-value=", 10));
+    let pairs = (0..arg_value(args, "--pairs=", 150))
+        .map(|_| format!("{}\n{}", generator.packet(), generator.packet()))
+        .collect::<Vec<_>>();
+    println!("{}", pairs.join("\n\n"));
+}
+
+fn divider_packets<A: Atom>(args: &[String]) -> Result<Vec<Packet<A>>, &'static str> {
+    let mut dividers: Vec<_> = args
         .iter()
+        .filter_map(|a| a.strip_prefix("--divider="))
+        .collect();
+    if dividers.is_empty() {
+        dividers = vec!["[[2]]", "[[6]]"];
+    }
+    dividers.iter().map(|d| d.parse()).collect()
+}
+
+fn solve<A: Atom>(input: &str, args: &[String]) {
+    if args.first().map(String::as_str) == Some("sort") {
+        sort_packets::<A>(input, args);
+        return;
+    }
+
+    let ans1 = input
+        .split("\n\n")
+        .map(|pair| {
+            let mut lines = pair.lines();
+            let left = lines.next().unwrap_or_default();
+            let right = lines.next().unwrap_or_default();
+            A::compare_text(left, right).unwrap_or_else(|| {
+                let left: Packet<A> = left.parse().unwrap();
+                let right: Packet<A> = right.parse().unwrap();
+                left.packet_cmp(&right)
+            })
+        })
         .enumerate()
-        .filter(|(_i, p)| p.0 < p.1)
-        .fold(0, |acc, (i, _p)| acc + i + 1);
+        .filter(|(_i, o)| o.is_lt())
+        .fold(0, |acc, (i, _o)| acc + i + 1);
     println!("Part One: {}", ans1);
 
-    let mut signals: BTreeSet<_> = pairs.into_iter().flat_map(|(a, b)| vec![a, b]).collect();
-    let divider_packet_1 = Item::L(vec![Item::L(vec![Item::V(2)])]);
-    let divider_packet_2 = Item::L(vec![Item::L(vec![Item::V(6)])]);
-    signals.insert(divider_packet_1.clone());
-    signals.insert(divider_packet_2.clone());
-    let ans2 =
-        signals.range(..=divider_packet_1).count() * signals.range(..=divider_packet_2).count();
+    let pairs = parser::item_pairs::<A>(input).unwrap().1;
+    if args.iter().any(|a| a == "--explain") {
+        for (i, (left, right)) in pairs.iter().enumerate() {
+            println!(
+                "== Pair {} ==\n{}",
+                i + 1,
+                explain::compare_explain(left, right)
+            );
+        }
+    }
+
+    let mut packets: SortedPackets<A> = pairs.into_iter().flat_map(|(a, b)| [a, b]).collect();
+    let divider_packets = match divider_packets::<A>(args) {
+        Ok(v) => v,
+        Err(e) => {
+            println!("Part Two: {} for divider packets", e);
+            return;
+        }
+    };
+    let ans2: usize = insert_dividers(&mut packets, &divider_packets)
+        .iter()
+        .product();
     println!("Part Two: {}", ans2);
 }
 
+// Returns the 1-based index of every divider, shifted by the dividers inserted after it.
+fn insert_dividers<A: Atom>(packets: &mut SortedPackets<A>, dividers: &[Packet<A>]) -> Vec<usize> {
+    let mut positions: Vec<usize> = vec![];
+    for divider in dividers {
+        let position = packets.insert(divider.clone());
+        for p in positions.iter_mut().filter(|p| **p >= position) {
+            *p += 1;
+        }
+        positions.push(position);
+    }
+    positions
+}
+
+fn sort_packets<A: Atom>(input: &str, args: &[String]) {
+    let pairs = parser::item_pairs::<A>(input).unwrap().1;
+    let mut packets: SortedPackets<A> = pairs.into_iter().flat_map(|(a, b)| [a, b]).collect();
+    let divider_packets = match divider_packets::<A>(args) {
+        Ok(v) => v,
+        Err(e) => panic!("{} for divider packets", e),
+    };
+    let positions = insert_dividers(&mut packets, &divider_packets);
+    let mut marked = vec![false; packets.len()];
+    for &position in positions.iter() {
+        marked[position - 1] = true;
+    }
+    for (i, packet) in packets.iter().enumerate() {
+        match marked[i] {
+            true => println!("{}: {} (divider)", i + 1, packet),
+            false => println!("{}: {}", i + 1, packet),
+        }
+    }
+    for (divider_packet, position) in divider_packets.iter().zip(positions) {
+        println!("Divider {} lands at {}", divider_packet, position);
+    }
+    for query in args.iter().filter_map(|a| a.strip_prefix("--rank=")) {
+        match query.parse::<Packet<A>>() {
+            Ok(packet) => println!("Rank of {}: {}", packet, packets.rank(&packet)),
+            Err(e) => println!("Rank of {}: {}", query, e),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -69,55 +309,763 @@ mod tests {
     fn eq_value_value() {
         let a = Item::V(4);
         let b = Item::V(4);
-        assert!(a == b);
+        assert_eq!(a.packet_cmp(&b), Ordering::Equal);
     }
     #[test]
     fn ne_value_value() {
         let a = Item::V(4);
         let b = Item::V(5);
-        assert!(a < b);
+        assert_eq!(a.packet_cmp(&b), Ordering::Less);
     }
     #[test]
     fn eq_list_list() {
         let a = Item::L(vec![Item::L(vec![Item::V(4), Item::V(5)]), Item::V(5)]);
         let b = Item::L(vec![Item::L(vec![Item::V(4), Item::V(5)]), Item::V(5)]);
-        assert!(a == b);
+        assert_eq!(a.packet_cmp(&b), Ordering::Equal);
     }
     #[test]
     fn ne_list_list_lack_of_item() {
         let a = Item::L(vec![Item::L(vec![Item::V(4), Item::V(5)]), Item::V(5)]);
         let b = Item::L(vec![Item::L(vec![Item::V(4), Item::V(5)])]);
-        assert!(a > b);
+        assert_eq!(a.packet_cmp(&b), Ordering::Greater);
     }
     #[test]
     fn ne_list_list_nest() {
         let a = Item::L(vec![Item::L(vec![Item::L(vec![])])]);
         let b = Item::L(vec![Item::L(vec![])]);
-        assert!(a > b);
+        assert_eq!(a.packet_cmp(&b), Ordering::Greater);
     }
     #[test]
     fn ne_list_list_ne_value() {
         let a = Item::L(vec![Item::V(4), Item::V(5)]);
         let b = Item::L(vec![Item::V(6), Item::V(5)]);
-        assert!(a < b);
+        assert_eq!(a.packet_cmp(&b), Ordering::Less);
     }
     #[test]
     fn eq_value_list() {
         let a = Item::V(4);
         let b = Item::L(vec![Item::V(4)]);
-        assert!(a == b);
+        assert_eq!(a.packet_cmp(&b), Ordering::Equal);
     }
     #[test]
     fn ne_value_list_value_greater() {
         let a = Item::V(5);
         let b = Item::L(vec![Item::V(4)]);
-        assert!(a > b);
+        assert_eq!(a.packet_cmp(&b), Ordering::Greater);
     }
     #[test]
     fn ne_value_list_list_greater() {
         let a = Item::V(4);
         let b = Item::L(vec![Item::V(5)]);
-        assert!(a < b);
+        assert_eq!(a.packet_cmp(&b), Ordering::Less);
+    }
+    #[test]
+    fn structural_eq_differs_from_packet_order() {
+        let a = Item::L(vec![Item::L(vec![Item::V(4)])]);
+        let b = Item::L(vec![Item::V(4)]);
+        let c = Item::V(4);
+        assert_ne!(a, b);
+        assert_ne!(b, c);
+        assert_eq!(PacketOrd(&a), PacketOrd(&b));
+        assert_eq!(PacketOrd(&b), PacketOrd(&c));
+        assert!(PacketOrd(&c) < PacketOrd(&Item::V(5)));
+    }
+    #[test]
+    fn sorted_packets_keep_equal_packets() {
+        let packets: SortedPackets = ["[[4]]", "[4]", "[5]", "[3]", "[[4]]"]
+            .iter()
+            .map(|p| p.parse().unwrap())
+            .collect();
+        assert_eq!(packets.len(), 5);
+        assert_eq!(
+            packets.iter().map(|p| p.to_string()).collect::<Vec<_>>(),
+            vec!["[3]", "[[4]]", "[4]", "[[4]]", "[5]"]
+        );
+        assert_eq!(packets.rank(&"[4]".parse().unwrap()), 2);
+        assert_eq!(packets.rank(&"[5]".parse().unwrap()), 5);
+        assert_eq!(packets.rank(&"[6]".parse().unwrap()), 6);
+    }
+    #[test]
+    fn dividers_keep_their_own_positions() {
+        let mut packets: SortedPackets = ["[1]", "[[2]]", "[3]"]
+            .iter()
+            .map(|p| p.parse().unwrap())
+            .collect();
+        let dividers: Vec<Item> = ["[[2]]", "[[2]]", "[0]"]
+            .iter()
+            .map(|p| p.parse().unwrap())
+            .collect();
+        assert_eq!(insert_dividers(&mut packets, &dividers), vec![4, 5, 1]);
+        assert_eq!(
+            packets.iter().map(|p| p.to_string()).collect::<Vec<_>>(),
+            vec!["[0]", "[1]", "[[2]]", "[[2]]", "[[2]]", "[3]"]
+        );
+        let mut packets: SortedPackets = SortedPackets::from_iter(vec![]);
+        assert_eq!(packets.insert("[4]".parse().unwrap()), 1);
+        assert_eq!(packets.insert("[[4]]".parse().unwrap()), 2);
+    }
+    #[test]
+    fn divider_packets_from_args() {
+        let defaults = divider_packets::<usize>(&[]).unwrap();
+        assert_eq!(
+            defaults.iter().map(|p| p.to_string()).collect::<Vec<_>>(),
+            vec!["[[2]]", "[[6]]"]
+        );
+        let args = vec!["sort".to_string(), "--divider=[[7]]".to_string()];
+        assert_eq!(
+            divider_packets::<usize>(&args).unwrap(),
+            vec![Item::L(vec![Item::L(vec![Item::V(7)])])]
+        );
+        assert!(divider_packets::<usize>(&["--divider=[7".to_string()]).is_err());
+    }
+    #[test]
+    fn packet_cmp_is_total_order() {
+        let mut generator = generator::PacketGenerator::new(34, 3, 3).with_max_value(3);
+        let packets: Vec<_> = (0..60).map(|_| generator.packet()).collect();
+        for a in packets.iter() {
+            assert_eq!(a.packet_cmp(a), Ordering::Equal);
+            for b in packets.iter() {
+                let ab = a.packet_cmp(b);
+                assert_eq!(ab, b.packet_cmp(a).reverse(), "{} {}", a, b);
+                for c in packets.iter() {
+                    if ab.is_le() && b.packet_cmp(c).is_le() {
+                        assert!(a.packet_cmp(c).is_le(), "{} {} {}", a, b, c);
+                    }
+                }
+            }
+        }
+    }
+    #[test]
+    fn parser_round_trips_with_display() {
+        let mut generator = generator::PacketGenerator::new(2022, 5, 5).with_max_value(100);
+        for _ in 0..1000 {
+            let packet = generator.packet();
+            assert_eq!(packet.to_string().parse::<Item>(), Ok(packet));
+        }
+    }
+    #[test]
+    fn display_round_trip() {
+        for packet in ["[]", "[1,[2,[3,[4,[5,6,7]]]],8,9]", "[[[]],10]"] {
+            assert_eq!(packet.parse::<Item>().unwrap().to_string(), packet);
+        }
+        assert!("[1,2".parse::<Item>().is_err());
+        assert!("[1,2]x".parse::<Item>().is_err());
+    }
+    #[test]
+    fn json_round_trip() {
+        let item: Item = "[1,[2,[]],3]".parse().unwrap();
+        let value = Value::from(&item);
+        assert_eq!(value, serde_json::json!([1, [2, []], 3]));
+        assert_eq!(Item::try_from(&value).unwrap().to_string(), "[1,[2,[]],3]");
+        assert!(Item::try_from(&serde_json::json!([1, -2])).is_err());
+        assert!(Item::try_from(&serde_json::json!(["a"])).is_err());
+    }
+    #[test]
+    fn serde_round_trip() {
+        let item: Item = "[[4,4],4,[]]".parse().unwrap();
+        let json = serde_json::to_string(&item).unwrap();
+        assert_eq!(json, "[[4,4],4,[]]");
+        let parsed: Item = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, item);
+    }
+}
+
+mod atom {
+    use nom::{
+        branch::alt,
+        character::complete::{char, digit1, i64 as nom_i64, none_of},
+        combinator::{map_res, opt, recognize},
+        multi::fold_many0,
+        sequence::{delimited, pair, preceded},
+        IResult,
+    };
+    use num_bigint::BigInt;
+    use std::{cmp::Ordering, fmt, hash::Hash};
+
+    pub trait Atom: Clone + fmt::Debug + Eq + Hash {
+        fn atom_cmp(&self, other: &Self) -> Ordering;
+        fn parse_atom(input: &str) -> IResult<&str, Self>;
+        fn fmt_atom(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result;
+        fn compare_text(_left: &str, _right: &str) -> Option<Ordering> {
+            None
+        }
+    }
+    impl Atom for usize {
+        fn atom_cmp(&self, other: &Self) -> Ordering {
+            self.cmp(other)
+        }
+        fn parse_atom(input: &str) -> IResult<&str, Self> {
+            map_res(digit1, |s: &str| s.parse::<usize>())(input)
+        }
+        fn fmt_atom(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{}", self)
+        }
+        fn compare_text(left: &str, right: &str) -> Option<Ordering> {
+            super::stream::compare(left, right).ok()
+        }
+    }
+    impl Atom for i64 {
+        fn atom_cmp(&self, other: &Self) -> Ordering {
+            self.cmp(other)
+        }
+        fn parse_atom(input: &str) -> IResult<&str, Self> {
+            nom_i64(input)
+        }
+        fn fmt_atom(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{}", self)
+        }
+    }
+    impl Atom for BigInt {
+        fn atom_cmp(&self, other: &Self) -> Ordering {
+            self.cmp(other)
+        }
+        fn parse_atom(input: &str) -> IResult<&str, Self> {
+            map_res(recognize(pair(opt(char('-')), digit1)), |s: &str| {
+                s.parse::<BigInt>()
+            })(input)
+        }
+        fn fmt_atom(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{}", self)
+        }
+    }
+    impl Atom for String {
+        fn atom_cmp(&self, other: &Self) -> Ordering {
+            self.cmp(other)
+        }
+        fn parse_atom(input: &str) -> IResult<&str, Self> {
+            delimited(
+                char('"'),
+                fold_many0(
+                    alt((
+                        none_of("\\\""),
+                        preceded(char('\\'), alt((char('\\'), char('"')))),
+                    )),
+                    String::new,
+                    |mut acc, c| {
+                        acc.push(c);
+                        acc
+                    },
+                ),
+                char('"'),
+            )(input)
+        }
+        fn fmt_atom(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "\"")?;
+            for c in self.chars() {
+                if c == '"' || c == '\\' {
+                    write!(f, "\\")?;
+                }
+                write!(f, "{}", c)?;
+            }
+            write!(f, "\"")
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::super::Packet;
+        use super::*;
+
+        fn parse<A: Atom>(packet: &str) -> Packet<A> {
+            packet.parse().unwrap()
+        }
+
+        #[test]
+        fn signed_atoms() {
+            assert_eq!(i64::parse_atom("-12,"), Ok((",", -12)));
+            let a: Packet<i64> = parse("[-1,[2]]");
+            let b: Packet<i64> = parse("[0]");
+            assert_eq!(a.packet_cmp(&b), Ordering::Less);
+            assert_eq!(a.to_string(), "[-1,[2]]");
+        }
+        #[test]
+        fn big_atoms() {
+            let a: Packet<BigInt> = parse("[18446744073709551616,-99999999999999999999]");
+            let b: Packet<BigInt> = parse("[18446744073709551615]");
+            assert_eq!(a.packet_cmp(&b), Ordering::Greater);
+            assert_eq!(
+                a.to_string(),
+                "[18446744073709551616,-99999999999999999999]"
+            );
+        }
+        #[test]
+        fn string_atoms() {
+            assert_eq!(
+                String::parse_atom(r#""a\"b\\c""#),
+                Ok(("", r#"a"b\c"#.to_string()))
+            );
+            assert_eq!(String::parse_atom(r#""""#), Ok(("", String::new())));
+            let a: Packet<String> = parse(r#"["apple",["b\"c"]]"#);
+            let b: Packet<String> = parse(r#"["apple","b"]"#);
+            assert_eq!(a.packet_cmp(&b), Ordering::Greater);
+            assert_eq!(a.to_string(), r#"["apple",["b\"c"]]"#);
+            assert!(r#"["unterminated]"#.parse::<Packet<String>>().is_err());
+        }
+    }
+}
+
+mod explain {
+    use super::{atom::Atom, Packet};
+    use std::{cmp::Ordering, fmt};
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Side {
+        Left,
+        Right,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Step {
+        Index(usize),
+        Promote(Side),
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Rule {
+        IntegerComparison,
+        RanOutOfItems(Side),
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct Explanation<A = usize> {
+        pub ordering: Ordering,
+        pub path: Vec<Step>,
+        pub rule: Option<Rule>,
+        // The innermost promotion on `path`, if the decision needed one.
+        pub promoted: Option<Side>,
+        pub left: Packet<A>,
+        pub right: Packet<A>,
+    }
+    impl<A: Atom> fmt::Display for Explanation<A> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let path = self
+                .path
+                .iter()
+                .map(|step| match step {
+                    Step::Index(i) => i.to_string(),
+                    Step::Promote(Side::Left) => "promote left".to_string(),
+                    Step::Promote(Side::Right) => "promote right".to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join(" > ");
+            writeln!(f, "At [{}]: compare {} vs {}", path, self.left, self.right)?;
+            match self.promoted {
+                Some(Side::Left) => writeln!(f, "Left value promoted to a list")?,
+                Some(Side::Right) => writeln!(f, "Right value promoted to a list")?,
+                None => (),
+            }
+            match self.rule {
+                Some(Rule::IntegerComparison) => write!(f, "Integer comparison")?,
+                Some(Rule::RanOutOfItems(Side::Left)) => write!(f, "Left side ran out of items")?,
+                Some(Rule::RanOutOfItems(Side::Right)) => write!(f, "Right side ran out of items")?,
+                None => return write!(f, "Packets are equivalent"),
+            }
+            match self.ordering {
+                Ordering::Less => write!(f, ", so inputs are in the right order"),
+                _ => write!(f, ", so inputs are not in the right order"),
+            }
+        }
+    }
+
+    type Decision<A> = (Ordering, Rule, Packet<A>, Packet<A>);
+
+    fn decide<A: Atom>(
+        left: &Packet<A>,
+        right: &Packet<A>,
+        path: &mut Vec<Step>,
+    ) -> Option<Decision<A>> {
+        match (left, right) {
+            (Packet::V(a), Packet::V(b)) => match a.atom_cmp(b) {
+                Ordering::Equal => None,
+                o => Some((o, Rule::IntegerComparison, left.clone(), right.clone())),
+            },
+            (Packet::L(a), Packet::L(b)) => {
+                for (i, (x, y)) in a.iter().zip(b).enumerate() {
+                    path.push(Step::Index(i));
+                    if let Some(decision) = decide(x, y, path) {
+                        return Some(decision);
+                    }
+                    path.pop();
+                }
+                let rule = match a.len().cmp(&b.len()) {
+                    Ordering::Equal => return None,
+                    Ordering::Less => Rule::RanOutOfItems(Side::Left),
+                    Ordering::Greater => Rule::RanOutOfItems(Side::Right),
+                };
+                Some((a.len().cmp(&b.len()), rule, left.clone(), right.clone()))
+            }
+            (Packet::V(_), Packet::L(_)) => {
+                path.push(Step::Promote(Side::Left));
+                let decision = decide(&Packet::L(vec![left.clone()]), right, path);
+                if decision.is_none() {
+                    path.pop();
+                }
+                decision
+            }
+            (Packet::L(_), Packet::V(_)) => {
+                path.push(Step::Promote(Side::Right));
+                let decision = decide(left, &Packet::L(vec![right.clone()]), path);
+                if decision.is_none() {
+                    path.pop();
+                }
+                decision
+            }
+        }
+    }
+
+    pub fn compare_explain<A: Atom>(left: &Packet<A>, right: &Packet<A>) -> Explanation<A> {
+        let mut path = vec![];
+        match decide(left, right, &mut path) {
+            Some((ordering, rule, left, right)) => Explanation {
+                ordering,
+                promoted: path.iter().rev().find_map(|step| match step {
+                    Step::Promote(side) => Some(*side),
+                    Step::Index(_) => None,
+                }),
+                path,
+                rule: Some(rule),
+                left,
+                right,
+            },
+            None => Explanation {
+                ordering: Ordering::Equal,
+                path,
+                rule: None,
+                promoted: None,
+                left: left.clone(),
+                right: right.clone(),
+            },
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::super::Item;
+        use super::*;
+
+        fn explain(left: &str, right: &str) -> Explanation {
+            compare_explain(&left.parse().unwrap(), &right.parse().unwrap())
+        }
+
+        #[test]
+        fn explain_integer_comparison() {
+            let e = explain("[1,1,3,1,1]", "[1,1,5,1,1]");
+            assert_eq!(e.ordering, Ordering::Less);
+            assert_eq!(e.path, vec![Step::Index(2)]);
+            assert_eq!(e.rule, Some(Rule::IntegerComparison));
+            assert_eq!(e.promoted, None);
+            assert_eq!((e.left, e.right), (Item::V(3), Item::V(5)));
+        }
+        #[test]
+        fn explain_list_promotion() {
+            let e = explain("[[1],[2,3,4]]", "[[1],4]");
+            assert_eq!(e.ordering, Ordering::Less);
+            assert_eq!(
+                e.path,
+                vec![Step::Index(1), Step::Promote(Side::Right), Step::Index(0)]
+            );
+            assert_eq!(e.rule, Some(Rule::IntegerComparison));
+            assert_eq!(e.promoted, Some(Side::Right));
+            assert_eq!((e.left, e.right), (Item::V(2), Item::V(4)));
+
+            let e = explain("[9]", "[[8,7,6]]");
+            assert_eq!(e.ordering, Ordering::Greater);
+            assert_eq!(
+                e.path,
+                vec![Step::Index(0), Step::Promote(Side::Left), Step::Index(0)]
+            );
+            assert_eq!(e.promoted, Some(Side::Left));
+
+            let e = explain("[[1,2]]", "[1]");
+            assert_eq!(e.rule, Some(Rule::RanOutOfItems(Side::Right)));
+            assert_eq!(e.promoted, Some(Side::Right));
+            assert!(e.to_string().contains("Right value promoted to a list"));
+        }
+        #[test]
+        fn explain_ran_out_of_items() {
+            let e = explain("[[4,4],4,4]", "[[4,4],4,4,4]");
+            assert_eq!(e.ordering, Ordering::Less);
+            assert!(e.path.is_empty());
+            assert_eq!(e.rule, Some(Rule::RanOutOfItems(Side::Left)));
+
+            let e = explain("[[[]]]", "[[]]");
+            assert_eq!(e.ordering, Ordering::Greater);
+            assert_eq!(e.path, vec![Step::Index(0)]);
+            assert_eq!(e.rule, Some(Rule::RanOutOfItems(Side::Right)));
+            assert_eq!(e.left.to_string(), "[[]]");
+            assert_eq!(e.right.to_string(), "[]");
+        }
+        #[test]
+        fn explain_equivalent_packets() {
+            let e = explain("[[4]]", "[4]");
+            assert_eq!(e.ordering, Ordering::Equal);
+            assert!(e.path.is_empty());
+            assert_eq!(e.rule, None);
+        }
+        #[test]
+        fn explain_agrees_with_packet_cmp() {
+            let input = include_str!("../../input/13-sample.txt");
+            for (left, right) in super::super::parser::item_pairs::<usize>(input).unwrap().1 {
+                assert_eq!(
+                    compare_explain(&left, &right).ordering,
+                    left.packet_cmp(&right)
+                );
+            }
+        }
+    }
+}
+
+mod generator {
+    use super::Item;
+    use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+    pub struct PacketGenerator {
+        rng: SmallRng,
+        max_depth: usize,
+        max_width: usize,
+        max_value: usize,
+    }
+    impl PacketGenerator {
+        // Every packet is a list, so `max_depth` must be at least 1.
+        pub fn new(seed: u64, max_depth: usize, max_width: usize) -> Self {
+            assert!(max_depth > 0, "Packets need a depth of at least 1");
+            Self {
+                rng: SmallRng::seed_from_u64(seed),
+                max_depth,
+                max_width,
+                max_value: 10,
+            }
+        }
+        pub fn with_max_value(mut self, max_value: usize) -> Self {
+            self.max_value = max_value;
+            self
+        }
+        pub fn packet(&mut self) -> Item {
+            self.list(self.max_depth)
+        }
+        // A list nests at most `depth` lists deep, counting itself.
+        fn list(&mut self, depth: usize) -> Item {
+            let width = self.rng.gen_range(0, self.max_width + 1);
+            Item::L((0..width).map(|_| self.item(depth - 1)).collect())
+        }
+        fn item(&mut self, depth: usize) -> Item {
+            if depth == 0 || self.rng.gen_bool(0.5) {
+                Item::V(self.rng.gen_range(0, self.max_value + 1))
+            } else {
+                self.list(depth)
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn depth(item: &Item) -> usize {
+            match item {
+                Item::V(_) => 0,
+                Item::L(items) => 1 + items.iter().map(depth).max().unwrap_or(0),
+            }
+        }
+        fn max_width(item: &Item) -> usize {
+            match item {
+                Item::V(_) => 0,
+                Item::L(items) => items.iter().map(max_width).fold(items.len(), usize::max),
+            }
+        }
+
+        #[test]
+        fn generator_is_seeded() {
+            let mut a = PacketGenerator::new(7, 3, 4);
+            let mut b = PacketGenerator::new(7, 3, 4);
+            for _ in 0..100 {
+                assert_eq!(a.packet(), b.packet());
+            }
+        }
+        #[test]
+        fn generator_respects_limits() {
+            let mut generator = PacketGenerator::new(1, 3, 4).with_max_value(2);
+            for _ in 0..1000 {
+                let packet = generator.packet();
+                assert!(depth(&packet) <= 3);
+                assert!(max_width(&packet) <= 4);
+                assert!(!packet.to_string().contains('3'));
+            }
+        }
+        #[test]
+        fn generator_depth_one_is_flat() {
+            let mut generator = PacketGenerator::new(5, 1, 4);
+            for _ in 0..100 {
+                assert_eq!(depth(&generator.packet()), 1);
+            }
+        }
+    }
+}
+
+mod stream {
+    use std::cmp::Ordering;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Token {
+        Open,
+        Close,
+        Value(usize),
+        End,
+    }
+
+    struct Tokens<'a> {
+        bytes: &'a [u8],
+        pos: usize,
+        pending_value: Option<usize>,
+        pending_closes: usize,
+    }
+    impl<'a> Tokens<'a> {
+        fn new(packet: &'a str) -> Self {
+            Self {
+                bytes: packet.as_bytes(),
+                pos: 0,
+                pending_value: None,
+                pending_closes: 0,
+            }
+        }
+        // Treats `value` as if it had been written `[value]`, whose `[` is already consumed.
+        fn promote(&mut self, value: usize) {
+            self.pending_value = Some(value);
+            self.pending_closes += 1;
+        }
+        fn next(&mut self) -> Result<Token, &'static str> {
+            if let Some(v) = self.pending_value.take() {
+                return Ok(Token::Value(v));
+            }
+            if self.pending_closes > 0 {
+                self.pending_closes -= 1;
+                return Ok(Token::Close);
+            }
+            while self.bytes.get(self.pos) == Some(&b',') {
+                self.pos += 1;
+            }
+            let token = match self.bytes.get(self.pos) {
+                None => return Ok(Token::End),
+                Some(b'[') => Token::Open,
+                Some(b']') => Token::Close,
+                Some(b'0'..=b'9') => {
+                    let mut v: usize = 0;
+                    while let Some(&c @ b'0'..=b'9') = self.bytes.get(self.pos) {
+                        v = v
+                            .checked_mul(10)
+                            .and_then(|v| v.checked_add((c - b'0') as usize))
+                            .ok_or("Value overflow")?;
+                        self.pos += 1;
+                    }
+                    return Ok(Token::Value(v));
+                }
+                Some(_) => return Err("Invalid character"),
+            };
+            self.pos += 1;
+            Ok(token)
+        }
+    }
+
+    pub fn compare(left: &str, right: &str) -> Result<Ordering, &'static str> {
+        let mut left = Tokens::new(left);
+        let mut right = Tokens::new(right);
+        loop {
+            match (left.next()?, right.next()?) {
+                (Token::End, Token::End) => return Ok(Ordering::Equal),
+                (Token::Open, Token::Open) | (Token::Close, Token::Close) => (),
+                (Token::Value(a), Token::Value(b)) => {
+                    if a != b {
+                        return Ok(a.cmp(&b));
+                    }
+                }
+                (Token::Value(a), Token::Open) => left.promote(a),
+                (Token::Open, Token::Value(b)) => right.promote(b),
+                (Token::Close | Token::End, _) => return Ok(Ordering::Less),
+                (_, Token::Close | Token::End) => return Ok(Ordering::Greater),
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::super::generator::PacketGenerator;
+        use super::super::Item;
+        use super::*;
+
+        #[test]
+        fn compare_sample_pairs() {
+            let cases = [
+                ("[1,1,3,1,1]", "[1,1,5,1,1]", Ordering::Less),
+                ("[[1],[2,3,4]]", "[[1],4]", Ordering::Less),
+                ("[9]", "[[8,7,6]]", Ordering::Greater),
+                ("[[4,4],4,4]", "[[4,4],4,4,4]", Ordering::Less),
+                ("[7,7,7,7]", "[7,7,7]", Ordering::Greater),
+                ("[]", "[3]", Ordering::Less),
+                ("[[[]]]", "[[]]", Ordering::Greater),
+                (
+                    "[1,[2,[3,[4,[5,6,7]]]],8,9]",
+                    "[1,[2,[3,[4,[5,6,0]]]],8,9]",
+                    Ordering::Greater,
+                ),
+                ("[[4]]", "4", Ordering::Equal),
+                ("[[[10]],3]", "[10,2]", Ordering::Greater),
+            ];
+            for (left, right, expected) in cases {
+                assert_eq!(compare(left, right), Ok(expected), "{} {}", left, right);
+                assert_eq!(
+                    compare(right, left),
+                    Ok(expected.reverse()),
+                    "{} {}",
+                    right,
+                    left
+                );
+            }
+            assert!(compare("[a]", "[1]").is_err());
+        }
+        #[test]
+        fn compare_agrees_with_packet_cmp() {
+            let mut generator = PacketGenerator::new(13, 4, 4);
+            for _ in 0..2000 {
+                let left = generator.packet();
+                let right = generator.packet();
+                assert_eq!(
+                    compare(&left.to_string(), &right.to_string()),
+                    Ok(left.packet_cmp(&right)),
+                    "{} {}",
+                    left,
+                    right
+                );
+            }
+        }
+        #[test]
+        #[ignore]
+        fn bench_compare_against_packet_cmp() {
+            let mut generator = PacketGenerator::new(2022, 6, 4);
+            let packets: Vec<_> = (0..200_000)
+                .map(|_| generator.packet().to_string())
+                .collect();
+
+            let now = std::time::Instant::now();
+            let items: Vec<Item> = packets.iter().map(|p| p.parse().unwrap()).collect();
+            let tree_lt = items
+                .windows(2)
+                .filter(|w| w[0].packet_cmp(&w[1]).is_lt())
+                .count();
+            let tree_elapsed = now.elapsed();
+
+            let now = std::time::Instant::now();
+            let stream_lt = packets
+                .windows(2)
+                .filter(|w| compare(&w[0], &w[1]).unwrap().is_lt())
+                .count();
+            let stream_elapsed = now.elapsed();
+
+            assert_eq!(tree_lt, stream_lt);
+            println!(
+                "parse + Item::packet_cmp: {:?}, stream::compare: {:?}",
+                tree_elapsed, stream_elapsed
+            );
+        }
     }
 }
 
@@ -126,29 +1074,28 @@ mod parser {
     use nom::{
         branch::alt,
         bytes::complete::tag,
-        character::complete::digit1,
-        combinator::{map, map_res},
+        combinator::map,
         multi::separated_list0,
         sequence::{preceded, terminated, tuple},
         IResult,
     };
 
-    fn value(input: &str) -> IResult<&str, Item> {
-        map(map_res(digit1, |s: &str| s.parse::<usize>()), Item::V)(input)
+    fn value<A: Atom>(input: &str) -> IResult<&str, Packet<A>> {
+        map(A::parse_atom, Packet::V)(input)
     }
-    fn item(input: &str) -> IResult<&str, Item> {
+    pub fn item<A: Atom>(input: &str) -> IResult<&str, Packet<A>> {
         map(
             preceded(
                 tag("["),
                 terminated(separated_list0(tag(","), alt((value, item))), tag("]")),
             ),
-            Item::L,
+            Packet::L,
         )(input)
     }
-    fn item_pair(input: &str) -> IResult<&str, (Item, Item)> {
+    fn item_pair<A: Atom>(input: &str) -> IResult<&str, PacketPair<A>> {
         tuple((item, preceded(tag("\n"), item)))(input)
     }
-    pub fn item_pairs(input: &str) -> IResult<&str, Vec<(Item, Item)>> {
+    pub fn item_pairs<A: Atom>(input: &str) -> IResult<&str, Vec<PacketPair<A>>> {
         separated_list0(tag("\n\n"), item_pair)(input)
     }
 
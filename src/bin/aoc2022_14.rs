@@ -1,10 +1,26 @@
-use crate::coordinate::{BoundingBox, Vector2D};
+use crate::{
+    cave::{Cave, Direction, FloorMode, Source, Step},
+    coordinate::{BoundingBox, Vector2D},
+    render::{FrameFormat, Renderer},
+};
+use std::{io::Write, str::FromStr};
 
-const N: usize = 1000;
-const POUR_X: usize = 500;
-const POUR_Y: usize = 0;
+const POUR_X: i64 = 500;
+const POUR_Y: i64 = 0;
 
 fn main() {
+    let args: Vec<_> = std::env::args().skip(1).collect();
+    if let Some(path) = args.iter().find_map(|a| a.strip_prefix("--load=")) {
+        let mut cave = load_cave(path);
+        if let Some(format) = args.iter().find_map(|a| a.strip_prefix("--render=")) {
+            animate(&mut cave, format.parse().unwrap(), &args);
+            return;
+        }
+        let resting = cave.resting_count() + cave.fill();
+        println!("Resting sand: {}", resting);
+        return;
+    }
+
     let mut input = String::new();
     let mut buffer = String::new();
     loop {
@@ -19,37 +35,188 @@ fn main() {
             Err(e) => panic!("{}", e),
         }
     }
-    let rock_paths = parser::vector_2d_paths(&input).unwrap().1;
-    let mut abyss_cave =
-        cave::Cave::from_rock_paths(&rock_paths, coordinate::Vector2D::new(POUR_X, POUR_Y));
-    let mut ans1 = 0;
-    while abyss_cave.advance() {
-        ans1 += 1;
+    let allow_diagonal = args.iter().any(|a| a == "--allow-diagonal");
+    let rock_paths = match rock_path::parse_rock_paths(&input, allow_diagonal) {
+        Ok(v) => v,
+        Err(e) => panic!("{}", e),
+    };
+    let bounding_box = BoundingBox::from_points(rock_paths.iter().flatten()).unwrap();
+    let solid_floor = FloorMode::Solid(bounding_box.max.y + 2);
+
+    let floor = match args.iter().any(|a| a == "--part=2") {
+        true => solid_floor,
+        false => FloorMode::Abyss,
+    };
+    if let Some(format) = args.iter().find_map(|a| a.strip_prefix("--render=")) {
+        let mut cave = Cave::from_rock_paths(&rock_paths, Vector2D::new(POUR_X, POUR_Y), floor);
+        configure(&mut cave, &args);
+        animate(&mut cave, format.parse().unwrap(), &args);
+        return;
+    }
+    let table = args.iter().find_map(|a| a.strip_prefix("--csv="));
+    if table.is_some() || args.iter().any(|a| a == "--stats") {
+        let mut cave = Cave::from_rock_paths(&rock_paths, Vector2D::new(POUR_X, POUR_Y), floor);
+        configure(&mut cave, &args);
+        cave.fill();
+        match table {
+            Some(table) => print!("{}", report::csv(&cave, table.parse().unwrap())),
+            None => println!("{}", report::summary(&cave)),
+        }
+        return;
+    }
+    if let Some(path) = args.iter().find_map(|a| a.strip_prefix("--save=")) {
+        let mut cave = Cave::from_rock_paths(&rock_paths, Vector2D::new(POUR_X, POUR_Y), floor);
+        configure(&mut cave, &args);
+        for _ in 0..arg_value(&args, "--grains=", usize::MAX) {
+            if !cave.advance() {
+                break;
+            }
+        }
+        save_cave(&cave, path);
+        return;
     }
+
+    let mut abyss_cave =
+        Cave::from_rock_paths(&rock_paths, Vector2D::new(POUR_X, POUR_Y), FloorMode::Abyss);
+    configure(&mut abyss_cave, &args);
+    let use_advance = args.iter().any(|a| a == "--advance");
+    let ans1 = if use_advance {
+        let mut count = 0;
+        while abyss_cave.advance() {
+            count += 1;
+        }
+        count
+    } else {
+        abyss_cave.fill()
+    };
     println!("Part One: {}", ans1);
 
     let mut floor_cave =
-        cave::Cave::from_rock_paths(&rock_paths, coordinate::Vector2D::new(POUR_X, POUR_Y));
-    let bounding_box = rock_paths
+        Cave::from_rock_paths(&rock_paths, Vector2D::new(POUR_X, POUR_Y), solid_floor);
+    configure(&mut floor_cave, &args);
+    let ans2 = if use_advance {
+        let mut count = 0;
+        while floor_cave.advance() {
+            count += 1;
+        }
+        count
+    } else {
+        floor_cave.count_reachable().unwrap()
+    };
+    println!("Part Two: {}", ans2);
+}
+
+fn arg_value<T: FromStr>(args: &[String], name: &str, default: T) -> T {
+    match args.iter().find_map(|a| a.strip_prefix(name)) {
+        Some(v) => match v.parse() {
+            Ok(v) => v,
+            Err(_) => panic!("Invalid value for {}{}", name, v),
+        },
+        None => default,
+    }
+}
+
+fn load_cave(path: &str) -> Cave {
+    let cave = match path.ends_with(".rle") {
+        true => Cave::from_rle_bytes(&std::fs::read(path).unwrap()),
+        false => Cave::from_text(&std::fs::read_to_string(path).unwrap()),
+    };
+    match cave {
+        Ok(cave) => cave,
+        Err(e) => panic!("{}: {}", path, e),
+    }
+}
+
+fn save_cave(cave: &Cave, path: &str) {
+    let data = match path.ends_with(".rle") {
+        true => cave.to_rle_bytes(),
+        false => cave.to_text().into_bytes(),
+    };
+    std::fs::write(path, data).unwrap();
+}
+
+fn configure(cave: &mut Cave, args: &[String]) {
+    let sources = args
         .iter()
-        .flatten()
-        .collect::<Vec<_>>()
-        .get_bounding_box();
-    floor_cave.add_rock_path(&[
-        Vector2D::new(0, bounding_box.1.y + 2),
-        Vector2D::new(N - 1, bounding_box.1.y + 2),
-    ]);
-    let mut ans2 = 1;
-    while floor_cave.advance() {
-        ans2 += 1;
+        .filter_map(|a| a.strip_prefix("--source="))
+        .map(|v| v.parse::<Source>())
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    if !sources.is_empty() {
+        cave.set_sources(&sources);
+    }
+    if let Some(fall) = args.iter().find_map(|a| a.strip_prefix("--fall=")) {
+        let fall_order = fall
+            .split(',')
+            .map(|v| v.parse::<Direction>())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        cave.set_fall_order(&fall_order);
+    }
+}
+
+fn animate(cave: &mut Cave, format: FrameFormat, args: &[String]) {
+    let renderer = Renderer::new(cave, arg_value(args, "--margin=", 2), format);
+    let per_step = args.iter().any(|a| a == "--frames-per=step");
+    let out_dir = args.iter().find_map(|a| a.strip_prefix("--out="));
+    let delay = std::time::Duration::from_millis(arg_value(args, "--delay=", 30));
+    let mut frame = 0;
+    let mut emit = |cave: &Cave| {
+        let bytes = renderer.render(cave);
+        match out_dir {
+            Some(dir) => {
+                let path = format!("{}/frame_{:06}.{}", dir, frame, format.extension());
+                if let Err(e) = std::fs::write(&path, bytes) {
+                    panic!("{}: {}", path, e);
+                }
+            }
+            None => {
+                let mut stdout = std::io::stdout().lock();
+                stdout.write_all(&bytes).unwrap();
+                stdout.flush().unwrap();
+                if format == FrameFormat::Ansi {
+                    std::thread::sleep(delay);
+                }
+            }
+        }
+        frame += 1;
+    };
+
+    if format == FrameFormat::Ansi && out_dir.is_none() {
+        print!("\x1b[2J");
+    }
+    emit(cave);
+    loop {
+        match cave.step() {
+            Step::Falling(at) => {
+                if per_step && renderer.shows(at) {
+                    emit(cave);
+                }
+            }
+            Step::Rested(_) => emit(cave),
+            Step::Abyss => {
+                emit(cave);
+                break;
+            }
+            Step::Blocked => break,
+        }
     }
-    println!("Part Two: {}", ans2);
 }
 
 mod cave {
-    use super::{coordinate::Vector2D, N};
-    #[derive(Clone, Copy)]
-    enum CellState {
+    use super::{
+        coordinate::{BoundingBox, Vector2D},
+        grid::{CellStore, FlatGrid},
+    };
+    use std::{
+        collections::{BTreeMap, HashSet},
+        fmt,
+        str::FromStr,
+    };
+
+    #[derive(Clone, Copy, Default)]
+    pub enum CellState {
+        #[default]
         Air,
         Rock,
         RestingSand,
@@ -68,56 +235,261 @@ mod cave {
             Ok(c)
         }
     }
+    impl TryFrom<char> for CellState {
+        type Error = &'static str;
+
+        fn try_from(c: char) -> Result<Self, Self::Error> {
+            match c {
+                '.' | '+' => Ok(CellState::Air),
+                '#' => Ok(CellState::Rock),
+                'o' => Ok(CellState::RestingSand),
+                '~' => Ok(CellState::FlowingSand),
+                _ => Err("Invalid cell"),
+            }
+        }
+    }
     impl CellState {
         fn is_stational(&self) -> bool {
             matches!(self, CellState::Rock | CellState::RestingSand)
         }
+        fn code(&self) -> u8 {
+            match self {
+                CellState::Air => 0,
+                CellState::Rock => 1,
+                CellState::RestingSand => 2,
+                CellState::FlowingSand => 3,
+            }
+        }
+        fn from_code(code: u8) -> Result<Self, &'static str> {
+            match code {
+                0 => Ok(CellState::Air),
+                1 => Ok(CellState::Rock),
+                2 => Ok(CellState::RestingSand),
+                3 => Ok(CellState::FlowingSand),
+                _ => Err("Invalid cell"),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum FloorMode {
+        Abyss,
+        Solid(i64),
+    }
+    impl fmt::Display for FloorMode {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                FloorMode::Abyss => write!(f, "abyss"),
+                FloorMode::Solid(y) => write!(f, "solid {}", y),
+            }
+        }
+    }
+    impl FromStr for FloorMode {
+        type Err = &'static str;
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s.split_once(' ') {
+                None if s == "abyss" => Ok(FloorMode::Abyss),
+                Some(("solid", y)) => y.parse().map(FloorMode::Solid).map_err(|_| "Invalid floor"),
+                _ => Err("Invalid floor"),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Step {
+        Falling(Vector2D),
+        Rested(Vector2D),
+        Abyss,
+        Blocked,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Direction {
+        Below,
+        BelowLeft,
+        BelowRight,
+    }
+    impl FromStr for Direction {
+        type Err = &'static str;
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "down" => Ok(Direction::Below),
+                "down-left" => Ok(Direction::BelowLeft),
+                "down-right" => Ok(Direction::BelowRight),
+                _ => Err("Unknown direction"),
+            }
+        }
+    }
+    impl fmt::Display for Direction {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                Direction::Below => write!(f, "down"),
+                Direction::BelowLeft => write!(f, "down-left"),
+                Direction::BelowRight => write!(f, "down-right"),
+            }
+        }
+    }
+    impl Direction {
+        fn offset(&self) -> Vector2D {
+            match self {
+                Direction::Below => Vector2D::new(0, 1),
+                Direction::BelowLeft => Vector2D::new(-1, 1),
+                Direction::BelowRight => Vector2D::new(1, 1),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Source {
+        pub at: Vector2D,
+        pub rate: usize,
+    }
+    impl fmt::Display for Source {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{},{},{}", self.at.x, self.at.y, self.rate)
+        }
     }
+    impl FromStr for Source {
+        type Err = &'static str;
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let values = s
+                .split(',')
+                .map(|v| v.parse::<i64>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| "Invalid source")?;
+            match values[..] {
+                [x, y] => Ok(Source {
+                    at: Vector2D::new(x, y),
+                    rate: 1,
+                }),
+                [x, y, rate] => Ok(Source {
+                    at: Vector2D::new(x, y),
+                    rate: usize::try_from(rate).map_err(|_| "Invalid source")?,
+                }),
+                _ => Err("Invalid source"),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct Overflow {
+        pub grain: usize,
+        pub source: usize,
+        pub path: Vec<Vector2D>,
+    }
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Column {
+        pub x: i64,
+        pub top: i64,
+        pub grains: usize,
+    }
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Pile {
+        pub grains: usize,
+        pub bounds: BoundingBox,
+    }
+
+    const DEFAULT_FALL_ORDER: [Direction; 3] = [
+        Direction::Below,
+        Direction::BelowLeft,
+        Direction::BelowRight,
+    ];
 
-    pub struct Cave {
-        cells: Vec<Vec<CellState>>,
-        pour_from: Vector2D,
+    // Largest area a saved RLE cave may cover, about 8000 by 8000 cells.
+    const MAX_RLE_CELLS: usize = 1 << 26;
+
+    // The store is a parameter only so it can be benchmarked against others.
+    pub type Cave = CaveWith<FlatGrid<CellState>>;
+    pub struct CaveWith<S> {
+        cells: S,
+        sources: Vec<Source>,
+        fall_order: Vec<Direction>,
+        abyss_y: i64,
+        floor: FloorMode,
+        falling: Option<(Vector2D, usize)>,
+        tick: usize,
+        trail: Vec<Vector2D>,
+        overflow: Option<Overflow>,
     }
-    impl Cave {
-        pub fn from_rock_paths(rock_paths: &[Vec<Vector2D>], pour_from: Vector2D) -> Self {
-            let cells = vec![vec![CellState::Air; N]; N];
-            let mut result = Self { cells, pour_from };
+    impl<S: CellStore<CellState>> CaveWith<S> {
+        pub fn from_rock_paths(
+            rock_paths: &[Vec<Vector2D>],
+            pour_from: Vector2D,
+            floor: FloorMode,
+        ) -> Self {
+            let mut result = Self {
+                cells: S::default(),
+                sources: vec![],
+                fall_order: DEFAULT_FALL_ORDER.to_vec(),
+                abyss_y: pour_from.y,
+                floor,
+                falling: None,
+                tick: 0,
+                trail: vec![],
+                overflow: None,
+            };
+            result.add_source(pour_from, 1);
             for path in rock_paths.iter() {
                 result.add_rock_path(path);
             }
             result
         }
+        pub fn add_source(&mut self, at: Vector2D, rate: usize) {
+            self.sources.push(Source { at, rate });
+        }
+        pub fn set_sources(&mut self, sources: &[Source]) {
+            self.sources = sources.to_vec();
+        }
+        pub fn set_fall_order(&mut self, fall_order: &[Direction]) {
+            self.fall_order = fall_order.to_vec();
+        }
         pub fn add_rock_path(&mut self, rock_path: &[Vector2D]) {
             for line in rock_path.windows(2) {
                 let mut current = line[0];
                 let end = line[1];
+                let d = end - current;
+                debug_assert!(
+                    d.manhattan() == d.chebyshev() || d.manhattan() == 2 * d.chebyshev(),
+                    "Rock segment is neither straight nor diagonal"
+                );
                 let dv = end.sig_vec(&current);
-                loop {
+                self.abyss_y = self.abyss_y.max(current.y).max(end.y);
+                for _ in 0..=d.chebyshev() {
                     if let Ok(cell) = self.get_mut_cell(current) {
                         *cell = CellState::Rock;
                     }
                     current += dv;
-                    if current == end + dv {
-                        break;
-                    }
                 }
             }
         }
         fn out_of_bounds(&self, at: Vector2D) -> bool {
-            at.y >= self.cells.len() || at.x >= self.cells[0].len()
+            match self.floor {
+                FloorMode::Abyss => at.y > self.abyss_y,
+                FloorMode::Solid(y) => at.y > y,
+            }
+        }
+        fn is_floor(&self, at: Vector2D) -> bool {
+            self.floor == FloorMode::Solid(at.y)
+        }
+        fn is_stational(&self, at: Vector2D) -> bool {
+            self.get_cell(at).map_or(true, |c| c.is_stational())
         }
         fn get_cell(&self, at: Vector2D) -> Result<&CellState, &'static str> {
             if self.out_of_bounds(at) {
                 Err("Out of bounds")
+            } else if self.is_floor(at) {
+                Ok(&CellState::Rock)
             } else {
-                Ok(&self.cells[at.y][at.x])
+                Ok(self.cells.get(at).unwrap_or(&CellState::Air))
             }
         }
         fn get_mut_cell(&mut self, at: Vector2D) -> Result<&mut CellState, &'static str> {
             if self.out_of_bounds(at) {
                 Err("Out of bounds")
+            } else if self.is_floor(at) {
+                Err("Floor")
             } else {
-                Ok(&mut self.cells[at.y][at.x])
+                Ok(self.cells.get_mut(at))
             }
         }
         fn try_flow(&mut self, sand_from: Vector2D, try_at: Vector2D) -> Result<(), &'static str> {
@@ -130,38 +502,513 @@ mod cave {
                 Ok(())
             }
         }
+        // Sources take turns in proportion to their rates, skipping those that are buried.
+        fn next_source(&mut self) -> Option<usize> {
+            let period: usize = self.sources.iter().map(|s| s.rate).sum();
+            for _ in 0..period {
+                let mut t = self.tick % period;
+                self.tick += 1;
+                let i = self
+                    .sources
+                    .iter()
+                    .position(|s| {
+                        let hit = t < s.rate;
+                        t = t.saturating_sub(s.rate);
+                        hit
+                    })
+                    .unwrap();
+                if !self.is_stational(self.sources[i].at) {
+                    return Some(i);
+                }
+            }
+            None
+        }
+        pub fn step(&mut self) -> Step {
+            let (sand_from, source) = match self.falling {
+                Some(v) => v,
+                None => {
+                    let i = match self.next_source() {
+                        Some(i) => i,
+                        None => return Step::Blocked,
+                    };
+                    let at = self.sources[i].at;
+                    *self.get_mut_cell(at).unwrap() = CellState::FlowingSand;
+                    self.falling = Some((at, i));
+                    self.trail = vec![at];
+                    return Step::Falling(at);
+                }
+            };
+            if self.out_of_bounds(sand_from + Vector2D::new(0, 1)) {
+                *self.get_mut_cell(sand_from).unwrap() = CellState::Air;
+                self.falling = None;
+                let path = std::mem::take(&mut self.trail);
+                self.record_overflow(source, path);
+                return Step::Abyss;
+            }
+            for i in 0..self.fall_order.len() {
+                let try_at = sand_from + self.fall_order[i].offset();
+                if self.try_flow(sand_from, try_at).is_ok() {
+                    self.falling = Some((try_at, source));
+                    self.trail.push(try_at);
+                    return Step::Falling(try_at);
+                }
+            }
+            *self.get_mut_cell(sand_from).unwrap() = CellState::RestingSand;
+            self.falling = None;
+            Step::Rested(sand_from)
+        }
         pub fn advance(&mut self) -> bool {
-            let mut sand_from = self.pour_from;
             loop {
-                let mut next_sand = sand_from;
-                let below = sand_from + Vector2D::new(0, 1);
-                if self.out_of_bounds(below) {
-                    return false;
-                }
-                let below_left = sand_from + Vector2D::new(!0, 1);
-                let below_right = sand_from + Vector2D::new(1, 1);
-                let try_ats = vec![below, below_left, below_right];
-                for try_at in try_ats {
-                    if self.try_flow(sand_from, try_at).is_err() {
-                        continue;
+                match self.step() {
+                    Step::Falling(_) => (),
+                    Step::Rested(_) => return true,
+                    Step::Abyss | Step::Blocked => return false,
+                }
+            }
+        }
+        // Drops one grain along `path`, resuming from its last free position.
+        fn drop_along(&mut self, path: &mut Vec<Vector2D>) -> Option<Vector2D> {
+            while let Some(&current) = path.last() {
+                if self.out_of_bounds(current + Vector2D::new(0, 1)) {
+                    return None;
+                }
+                match self
+                    .fall_order
+                    .iter()
+                    .map(|d| current + d.offset())
+                    .find(|&try_at| !self.is_stational(try_at))
+                {
+                    Some(next) => path.push(next),
+                    None => {
+                        *self.get_mut_cell(current).unwrap() = CellState::RestingSand;
+                        path.pop();
+                        return Some(current);
+                    }
+                }
+            }
+            None
+        }
+        pub fn fill(&mut self) -> usize {
+            let mut count = 0;
+            while self.falling.is_some() {
+                match self.step() {
+                    Step::Rested(_) => count += 1,
+                    Step::Abyss => return count,
+                    _ => (),
+                }
+            }
+            let mut paths = vec![vec![]; self.sources.len()];
+            while let Some(i) = self.next_source() {
+                let path: &mut Vec<Vector2D> = &mut paths[i];
+                if let Some(j) = path.iter().position(|&at| self.is_stational(at)) {
+                    path.truncate(j);
+                }
+                if path.is_empty() {
+                    path.push(self.sources[i].at);
+                }
+                match self.drop_along(path) {
+                    Some(_) => count += 1,
+                    None => {
+                        let path = path.clone();
+                        self.record_overflow(i, path);
+                        break;
+                    }
+                }
+            }
+            count
+        }
+        pub fn count_reachable(&self) -> Option<usize> {
+            let floor_y = match self.floor {
+                FloorMode::Abyss => return None,
+                FloorMode::Solid(y) => y,
+            };
+            // Sources with a zero rate never pour, see `next_source`.
+            let pouring: Vec<&Source> = self.sources.iter().filter(|s| s.rate > 0).collect();
+            let mut row: HashSet<i64> = HashSet::new();
+            let mut count = 0;
+            let top = match pouring.iter().map(|s| s.at.y).min() {
+                Some(y) => y,
+                None => return Some(0),
+            };
+            for y in top..floor_y {
+                for source in pouring.iter().filter(|s| s.at.y == y) {
+                    row.insert(source.at.x);
+                }
+                row.retain(|&x| !self.is_stational(Vector2D::new(x, y)));
+                count += row.len();
+                row = row
+                    .iter()
+                    .flat_map(|&x| {
+                        self.fall_order
+                            .iter()
+                            .map(move |d| (Vector2D::new(x, y) + d.offset()).x)
+                    })
+                    .collect();
+            }
+            Some(count)
+        }
+        pub fn extent(&self) -> BoundingBox {
+            let mut result = self.stored_extent();
+            if let FloorMode::Solid(y) = self.floor {
+                for source in self.sources.iter() {
+                    let spread = y - source.at.y;
+                    result = result.union(&BoundingBox::new(
+                        Vector2D::new(source.at.x - spread, source.at.y),
+                        Vector2D::new(source.at.x + spread, y),
+                    ));
+                }
+            }
+            result
+        }
+        pub fn char_at(&self, at: Vector2D) -> char {
+            match self.get_cell(at) {
+                Ok(CellState::Air) if self.sources.iter().any(|s| s.at == at) => '+',
+                Ok(cell) => (*cell).try_into().unwrap(),
+                Err(_) => ' ',
+            }
+        }
+        pub fn resting_count(&self) -> usize {
+            self.cells
+                .iter()
+                .filter(|(_at, cell)| matches!(cell, CellState::RestingSand))
+                .count()
+        }
+        fn stored_extent(&self) -> BoundingBox {
+            let sources = self.sources.iter().map(|s| s.at);
+            let cells = self
+                .cells
+                .iter()
+                .filter(|(_at, cell)| !matches!(cell, CellState::Air))
+                .map(|(at, _cell)| at);
+            BoundingBox::from_points(sources.chain(cells)).unwrap()
+        }
+        fn stored_cell(&self, at: Vector2D) -> CellState {
+            *self.cells.get(at).unwrap_or(&CellState::Air)
+        }
+        fn empty(floor: FloorMode, abyss_y: i64) -> Self {
+            Self {
+                cells: S::default(),
+                sources: vec![],
+                fall_order: vec![],
+                abyss_y,
+                floor,
+                falling: None,
+                tick: 0,
+                trail: vec![],
+                overflow: None,
+            }
+        }
+        pub fn to_text(&self) -> String {
+            let BoundingBox { min, max } = self.stored_extent();
+            let mut result = vec![
+                format!("origin {},{}", min.x, min.y),
+                format!("floor {}", self.floor),
+                format!("abyss {}", self.abyss_y),
+                format!("tick {}", self.tick),
+            ];
+            for source in self.sources.iter() {
+                result.push(format!("source {}", source));
+            }
+            let fall_order: Vec<_> = self.fall_order.iter().map(|d| d.to_string()).collect();
+            result.push(format!("fall {}", fall_order.join(",")));
+            if let Some((at, source)) = self.falling {
+                result.push(format!("falling {},{},{}", at.x, at.y, source));
+            }
+            result.push(String::new());
+            for y in min.y..=max.y {
+                let row: String = (min.x..=max.x)
+                    .map(|x| {
+                        let at = Vector2D::new(x, y);
+                        match self.stored_cell(at) {
+                            CellState::Air if self.sources.iter().any(|s| s.at == at) => '+',
+                            cell => cell.try_into().unwrap(),
+                        }
+                    })
+                    .collect();
+                result.push(row);
+            }
+            result.join("\n") + "\n"
+        }
+        pub fn from_text(text: &str) -> Result<Self, &'static str> {
+            let (header, grid) = text.split_once("\n\n").ok_or("Missing cave grid")?;
+            let mut result = Self::empty(FloorMode::Abyss, 0);
+            let mut origin = None;
+            let mut abyss_y = None;
+            let mut fall_order = None;
+            for line in header.lines() {
+                let (key, value) = line.split_once(' ').ok_or("Invalid header line")?;
+                let numbers = || {
+                    value
+                        .split(',')
+                        .map(|v| v.parse::<i64>())
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(|_| "Invalid number")
+                };
+                match key {
+                    "origin" => match numbers()?[..] {
+                        [x, y] => origin = Some(Vector2D::new(x, y)),
+                        _ => return Err("Invalid origin"),
+                    },
+                    "floor" => result.floor = value.parse()?,
+                    "abyss" => abyss_y = Some(value.parse().map_err(|_| "Invalid abyss")?),
+                    "tick" => result.tick = value.parse().map_err(|_| "Invalid tick")?,
+                    "source" => result.sources.push(value.parse()?),
+                    "fall" => {
+                        fall_order = Some(
+                            value
+                                .split(',')
+                                .map(|v| v.parse())
+                                .collect::<Result<_, _>>()?,
+                        )
+                    }
+                    "falling" => match numbers()?[..] {
+                        [x, y, source] => {
+                            let source = usize::try_from(source).map_err(|_| "Invalid source")?;
+                            result.falling = Some((Vector2D::new(x, y), source));
+                        }
+                        _ => return Err("Invalid falling grain"),
+                    },
+                    _ => return Err("Unknown header key"),
+                }
+            }
+            let origin = origin.ok_or("Missing origin")?;
+            for (dy, row) in grid.lines().enumerate() {
+                for (dx, c) in row.chars().enumerate() {
+                    let cell = CellState::try_from(c)?;
+                    if !matches!(cell, CellState::Air) {
+                        let at = origin + Vector2D::new(dx as i64, dy as i64);
+                        *result.cells.get_mut(at) = cell;
+                    }
+                }
+            }
+            // Hand-edited files may leave these out; use what `from_rock_paths` would.
+            result.fall_order = fall_order.unwrap_or_else(|| DEFAULT_FALL_ORDER.to_vec());
+            result.abyss_y = match abyss_y {
+                Some(y) => y,
+                None => {
+                    let rocks = result
+                        .cells
+                        .iter()
+                        .filter(|(_at, cell)| matches!(cell, CellState::Rock))
+                        .map(|(at, _cell)| at.y);
+                    let sources = result.sources.iter().map(|s| s.at.y);
+                    rocks.chain(sources).max().unwrap_or(0)
+                }
+            };
+            result.validate()
+        }
+        fn validate(self) -> Result<Self, &'static str> {
+            if self.sources.is_empty() {
+                return Err("Missing source");
+            }
+            if self.falling.is_some_and(|(_at, i)| i >= self.sources.len()) {
+                return Err("Falling grain refers to an unknown source");
+            }
+            Ok(self)
+        }
+        pub fn to_rle_bytes(&self) -> Vec<u8> {
+            let bounds = self.stored_extent();
+            let mut result = b"AOC14RLE".to_vec();
+            let mut push = |v: i64| result.extend(v.to_le_bytes());
+            push(bounds.min.x);
+            push(bounds.min.y);
+            push(bounds.width());
+            push(bounds.height());
+            push(self.abyss_y);
+            match self.floor {
+                FloorMode::Abyss => push(0),
+                FloorMode::Solid(y) => {
+                    push(1);
+                    push(y);
+                }
+            }
+            push(self.tick as i64);
+            match self.falling {
+                Some((at, source)) => {
+                    push(1);
+                    push(at.x);
+                    push(at.y);
+                    push(source as i64);
+                }
+                None => push(0),
+            }
+            push(self.sources.len() as i64);
+            for source in self.sources.iter() {
+                push(source.at.x);
+                push(source.at.y);
+                push(source.rate as i64);
+            }
+            push(self.fall_order.len() as i64);
+            for direction in self.fall_order.iter() {
+                push(*direction as i64);
+            }
+            let mut runs: Vec<(u32, u8)> = vec![];
+            for at in bounds.cells() {
+                let code = self.stored_cell(at).code();
+                match runs.last_mut() {
+                    Some((n, c)) if *c == code && *n < u32::MAX => *n += 1,
+                    _ => runs.push((1, code)),
+                }
+            }
+            for (n, code) in runs {
+                result.extend(n.to_le_bytes());
+                result.push(code);
+            }
+            result
+        }
+        pub fn from_rle_bytes(bytes: &[u8]) -> Result<Self, &'static str> {
+            let mut rest = bytes.strip_prefix(b"AOC14RLE").ok_or("Invalid magic")?;
+            let mut take = |n: usize| -> Result<&[u8], &'static str> {
+                if rest.len() < n {
+                    return Err("Unexpected end of data");
+                }
+                let (head, tail) = rest.split_at(n);
+                rest = tail;
+                Ok(head)
+            };
+            let mut next = || -> Result<i64, &'static str> {
+                Ok(i64::from_le_bytes(take(8)?.try_into().unwrap()))
+            };
+            let count = |v: i64| usize::try_from(v).map_err(|_| "Invalid count");
+            let origin = Vector2D::new(next()?, next()?);
+            let width = count(next()?)?;
+            let height = count(next()?)?;
+            let abyss_y = next()?;
+            let floor = match next()? {
+                0 => FloorMode::Abyss,
+                1 => FloorMode::Solid(next()?),
+                _ => return Err("Invalid floor"),
+            };
+            let mut result = Self::empty(floor, abyss_y);
+            result.tick = count(next()?)?;
+            match next()? {
+                0 => (),
+                1 => {
+                    let at = Vector2D::new(next()?, next()?);
+                    result.falling = Some((at, count(next()?)?));
+                }
+                _ => return Err("Invalid falling grain"),
+            }
+            for _ in 0..count(next()?)? {
+                let at = Vector2D::new(next()?, next()?);
+                result.sources.push(Source {
+                    at,
+                    rate: count(next()?)?,
+                });
+            }
+            for _ in 0..count(next()?)? {
+                result.fall_order.push(match next()? {
+                    0 => Direction::Below,
+                    1 => Direction::BelowLeft,
+                    2 => Direction::BelowRight,
+                    _ => return Err("Invalid direction"),
+                });
+            }
+            let total = width
+                .checked_mul(height)
+                .filter(|&n| n <= MAX_RLE_CELLS)
+                .ok_or("Cave too large")?;
+            if origin.x.checked_add(width as i64).is_none()
+                || origin.y.checked_add(height as i64).is_none()
+            {
+                return Err("Cave out of range");
+            }
+            let mut i = 0;
+            while i < total {
+                let n = u32::from_le_bytes(take(4)?.try_into().unwrap()) as usize;
+                let cell = CellState::from_code(take(1)?[0])?;
+                if i + n > total {
+                    return Err("Run past the end of the cave");
+                }
+                if !matches!(cell, CellState::Air) {
+                    for j in i..i + n {
+                        let at = origin + Vector2D::new((j % width) as i64, (j / width) as i64);
+                        *result.cells.get_mut(at) = cell;
+                    }
+                }
+                i += n;
+            }
+            if !rest.is_empty() {
+                return Err("Trailing data after the last run");
+            }
+            result.validate()
+        }
+        fn record_overflow(&mut self, source: usize, path: Vec<Vector2D>) {
+            if self.overflow.is_none() {
+                let grain = self.resting_count() + 1;
+                self.overflow = Some(Overflow {
+                    grain,
+                    source,
+                    path,
+                });
+            }
+        }
+        fn resting_sand(&self) -> impl Iterator<Item = Vector2D> + '_ {
+            self.cells
+                .iter()
+                .filter(|(_at, cell)| matches!(cell, CellState::RestingSand))
+                .map(|(at, _cell)| at)
+        }
+        pub fn first_overflow(&self) -> Option<&Overflow> {
+            self.overflow.as_ref()
+        }
+        pub fn columns(&self) -> Vec<Column> {
+            let mut columns: BTreeMap<i64, Column> = BTreeMap::new();
+            for at in self.resting_sand() {
+                let column = columns.entry(at.x).or_insert(Column {
+                    x: at.x,
+                    top: at.y,
+                    grains: 0,
+                });
+                column.top = column.top.min(at.y);
+                column.grains += 1;
+            }
+            columns.into_values().collect()
+        }
+        pub fn depth_histogram(&self) -> BTreeMap<i64, usize> {
+            let mut result = BTreeMap::new();
+            for at in self.resting_sand() {
+                *result.entry(at.y).or_insert(0) += 1;
+            }
+            result
+        }
+        // Piles are groups of resting sand touching each other, diagonals included.
+        pub fn largest_pile(&self) -> Option<Pile> {
+            let mut unvisited: HashSet<Vector2D> = self.resting_sand().collect();
+            let mut result: Option<Pile> = None;
+            while let Some(&start) = unvisited.iter().next() {
+                unvisited.remove(&start);
+                let mut pile = Pile {
+                    grains: 0,
+                    bounds: BoundingBox::new(start, start),
+                };
+                let mut stack = vec![start];
+                while let Some(at) = stack.pop() {
+                    pile.grains += 1;
+                    pile.bounds = pile.bounds.union(&BoundingBox::new(at, at));
+                    for dy in -1..=1 {
+                        for dx in -1..=1 {
+                            let next = at + Vector2D::new(dx, dy);
+                            if unvisited.remove(&next) {
+                                stack.push(next);
+                            }
+                        }
                     }
-                    next_sand = try_at;
-                    break;
                 }
-                if next_sand == sand_from {
-                    *self.get_mut_cell(sand_from).unwrap() = CellState::RestingSand;
-                    return next_sand != self.pour_from;
+                if result.is_none_or(|r| pile.grains > r.grains) {
+                    result = Some(pile);
                 }
-                sand_from = next_sand;
             }
+            result
         }
     }
-    impl std::fmt::Debug for Cave {
+    impl<S: CellStore<CellState>> std::fmt::Debug for CaveWith<S> {
         fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            let BoundingBox { min, max } = self.extent();
             let mut result = vec![];
-            for i in 0..self.cells.len() {
-                let row = (0..self.cells[0].len())
-                    .map(|j| self.cells[i][j].try_into().unwrap())
+            for i in min.y..=max.y {
+                let row = (min.x..=max.x)
+                    .map(|j| self.char_at(Vector2D::new(j, i)))
                     .collect::<Vec<char>>();
                 result.push(row.iter().collect::<String>());
             }
@@ -169,88 +1016,1176 @@ mod cave {
             write!(f, "\n{}", result)
         }
     }
-}
 
-mod coordinate {
-    use std::ops::{Add, AddAssign};
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::{
+            alloc::{GlobalAlloc, Layout, System},
+            collections::HashMap,
+            sync::atomic::{AtomicUsize, Ordering},
+        };
 
-    #[derive(Debug, PartialEq, Clone, Copy, Eq, PartialOrd, Ord)]
-    pub struct Vector2D {
-        pub x: usize,
-        pub y: usize,
-    }
-    impl Vector2D {
-        pub fn new(x: usize, y: usize) -> Self {
-            Self { x, y }
+        fn count_resting(cave: &mut Cave) -> usize {
+            let mut result = 0;
+            while cave.advance() {
+                result += 1;
+            }
+            result
         }
-        pub fn sig_vec(&self, other: &Self) -> Vector2D {
-            let dx = signum(self.x, other.x);
-            let dy = signum(self.y, other.y);
-            Self::new(dx, dy)
+
+        #[test]
+        fn sample_cave() {
+            let rock_paths = vec![
+                vec![
+                    Vector2D::new(498, 4),
+                    Vector2D::new(498, 6),
+                    Vector2D::new(496, 6),
+                ],
+                vec![
+                    Vector2D::new(503, 4),
+                    Vector2D::new(502, 4),
+                    Vector2D::new(502, 9),
+                    Vector2D::new(494, 9),
+                ],
+            ];
+            let mut cave =
+                Cave::from_rock_paths(&rock_paths, Vector2D::new(500, 0), FloorMode::Abyss);
+            assert_eq!(count_resting(&mut cave), 24);
+
+            let mut cave =
+                Cave::from_rock_paths(&rock_paths, Vector2D::new(500, 0), FloorMode::Solid(11));
+            assert_eq!(count_resting(&mut cave), 93);
+            assert!(cave
+                .cells
+                .iter()
+                .filter(|(_at, cell)| !matches!(cell, CellState::Air))
+                .all(|(at, _cell)| at.y < 11));
         }
-    }
-    impl AddAssign for Vector2D {
-        fn add_assign(&mut self, rhs: Self) {
-            *self = Self {
-                x: self.x.wrapping_add(rhs.x),
-                y: self.y.wrapping_add(rhs.y),
-            };
+        #[test]
+        fn fill_matches_advance() {
+            let inputs = [
+                include_str!("../../input/14-sample.txt"),
+                include_str!("../../input/14.txt"),
+            ];
+            for input in inputs {
+                let rock_paths = crate::rock_path::parse_rock_paths(input, false).unwrap();
+                let floor_y = rock_paths.iter().flatten().map(|v| v.y).max().unwrap() + 2;
+                for floor in [FloorMode::Abyss, FloorMode::Solid(floor_y)] {
+                    let mut advanced =
+                        Cave::from_rock_paths(&rock_paths, Vector2D::new(500, 0), floor);
+                    let expected = count_resting(&mut advanced);
+                    let mut filled =
+                        Cave::from_rock_paths(&rock_paths, Vector2D::new(500, 0), floor);
+                    if floor != FloorMode::Abyss {
+                        assert_eq!(filled.count_reachable(), Some(expected));
+                    }
+                    assert_eq!(filled.fill(), expected);
+                }
+            }
         }
-    }
-    impl Add for Vector2D {
-        type Output = Self;
-
-        fn add(self, rhs: Self) -> Self::Output {
-            Self::new(self.x.wrapping_add(rhs.x), self.y.wrapping_add(rhs.y))
+        #[test]
+        fn step_moves_one_cell() {
+            let rock_paths = vec![vec![Vector2D::new(499, 3), Vector2D::new(501, 3)]];
+            let mut cave =
+                Cave::from_rock_paths(&rock_paths, Vector2D::new(500, 0), FloorMode::Abyss);
+            assert_eq!(cave.step(), Step::Falling(Vector2D::new(500, 0)));
+            assert_eq!(cave.step(), Step::Falling(Vector2D::new(500, 1)));
+            assert_eq!(cave.step(), Step::Falling(Vector2D::new(500, 2)));
+            assert_eq!(cave.step(), Step::Rested(Vector2D::new(500, 2)));
+            assert_eq!(cave.step(), Step::Falling(Vector2D::new(500, 0)));
+            assert_eq!(cave.step(), Step::Falling(Vector2D::new(500, 1)));
+            assert_eq!(cave.step(), Step::Falling(Vector2D::new(499, 2)));
+            assert_eq!(cave.step(), Step::Falling(Vector2D::new(498, 3)));
+            assert_eq!(cave.step(), Step::Abyss);
+            assert_eq!(cave.char_at(Vector2D::new(498, 3)), '.');
         }
-    }
-    fn signum(a: usize, b: usize) -> usize {
-        match a.cmp(&b) {
-            std::cmp::Ordering::Less => !0,
-            std::cmp::Ordering::Equal => 0,
-            std::cmp::Ordering::Greater => 1,
+        #[test]
+        fn one_way_fall_order() {
+            let rock_paths = vec![vec![Vector2D::new(497, 3), Vector2D::new(503, 3)]];
+            let mut cave =
+                Cave::from_rock_paths(&rock_paths, Vector2D::new(500, 0), FloorMode::Abyss);
+            cave.set_fall_order(&[Direction::Below, Direction::BelowRight]);
+            assert_eq!(cave.fill(), 6);
+            assert_eq!(
+                format!("{:?}", cave),
+                "\n...o...\n...oo..\n...ooo.\n#######"
+            );
         }
-    }
-    pub trait BoundingBox {
-        fn get_bounding_box(&self) -> (Vector2D, Vector2D);
-    }
-    impl BoundingBox for Vec<&Vector2D> {
-        fn get_bounding_box(&self) -> (Vector2D, Vector2D) {
-            let max_x = self.iter().map(|v| v.x).max().unwrap();
-            let max_y = self.iter().map(|v| v.y).max().unwrap();
-            let min_x = self.iter().map(|v| v.x).min().unwrap();
-            let min_y = self.iter().map(|v| v.y).min().unwrap();
-            (Vector2D::new(min_x, min_y), Vector2D::new(max_x, max_y))
+        #[test]
+        fn sources_compete_by_rate() {
+            let rock_paths = vec![vec![Vector2D::new(490, 4), Vector2D::new(510, 4)]];
+            let mut cave =
+                Cave::from_rock_paths(&rock_paths, Vector2D::new(500, 0), FloorMode::Abyss);
+            cave.set_sources(&[
+                Source {
+                    at: Vector2D::new(495, 0),
+                    rate: 2,
+                },
+                Source {
+                    at: Vector2D::new(505, 0),
+                    rate: 1,
+                },
+            ]);
+            let mut rested = vec![];
+            for _ in 0..6 {
+                loop {
+                    match cave.step() {
+                        Step::Rested(at) => break rested.push(at.x < 500),
+                        Step::Falling(_) => (),
+                        step => panic!("{:?}", step),
+                    }
+                }
+            }
+            assert_eq!(rested, vec![true, true, false, true, true, false]);
         }
-    }
-}
+        #[test]
+        fn fill_and_reachable_with_several_sources() {
+            let input = include_str!("../../input/14-sample.txt");
+            let rock_paths = crate::rock_path::parse_rock_paths(input, false).unwrap();
+            let sources = [
+                Source {
+                    at: Vector2D::new(500, 0),
+                    rate: 1,
+                },
+                Source {
+                    at: Vector2D::new(490, 2),
+                    rate: 3,
+                },
+            ];
+            let mut advanced =
+                Cave::from_rock_paths(&rock_paths, Vector2D::new(500, 0), FloorMode::Solid(11));
+            advanced.set_sources(&sources);
+            let mut expected = 0;
+            while let Some(step) = Some(advanced.step()).filter(|s| *s != Step::Blocked) {
+                if let Step::Rested(_) = step {
+                    expected += 1;
+                }
+            }
+            let mut filled =
+                Cave::from_rock_paths(&rock_paths, Vector2D::new(500, 0), FloorMode::Solid(11));
+            filled.set_sources(&sources);
+            assert_eq!(filled.count_reachable(), Some(expected));
+            assert_eq!(filled.fill(), expected);
 
-mod parser {
-    use super::coordinate::Vector2D;
-    use nom::{
-        bytes::complete::tag,
-        character::complete::digit1,
-        combinator::{map, map_res},
-        multi::separated_list0,
-        sequence::{preceded, tuple},
-        IResult,
-    };
+            for floor in [FloorMode::Abyss, FloorMode::Solid(11)] {
+                let mut advanced = Cave::from_rock_paths(&rock_paths, Vector2D::new(500, 0), floor);
+                advanced.set_sources(&sources);
+                let mut filled = Cave::from_rock_paths(&rock_paths, Vector2D::new(500, 0), floor);
+                filled.set_sources(&sources);
+                assert_eq!(count_resting(&mut advanced), filled.fill());
+            }
 
-    fn parsed_usize(input: &str) -> IResult<&str, usize> {
-        map_res(digit1, |s: &str| s.parse::<usize>())(input)
+            let idle = [
+                sources[0],
+                Source {
+                    at: Vector2D::new(480, 0),
+                    rate: 0,
+                },
+            ];
+            let mut advanced =
+                Cave::from_rock_paths(&rock_paths, Vector2D::new(500, 0), FloorMode::Solid(11));
+            advanced.set_sources(&idle);
+            let mut filled =
+                Cave::from_rock_paths(&rock_paths, Vector2D::new(500, 0), FloorMode::Solid(11));
+            filled.set_sources(&idle);
+            assert_eq!(filled.count_reachable(), Some(93));
+            assert_eq!(count_resting(&mut advanced), 93);
+            assert_eq!(filled.fill(), 93);
+        }
+        #[test]
+        fn save_and_resume() {
+            let input = include_str!("../../input/14-sample.txt");
+            let rock_paths = crate::rock_path::parse_rock_paths(input, false).unwrap();
+            for floor in [FloorMode::Abyss, FloorMode::Solid(11)] {
+                let mut cave = Cave::from_rock_paths(&rock_paths, Vector2D::new(500, 0), floor);
+                let total = cave.fill();
+
+                let mut cave = Cave::from_rock_paths(&rock_paths, Vector2D::new(500, 0), floor);
+                for _ in 0..10 {
+                    cave.advance();
+                }
+                for _ in 0..3 {
+                    cave.step();
+                }
+                let text = cave.to_text();
+                let bytes = cave.to_rle_bytes();
+
+                let mut from_text = Cave::from_text(&text).unwrap();
+                assert_eq!(from_text.to_text(), text);
+                assert_eq!(from_text.resting_count() + from_text.fill(), total);
+
+                let mut from_bytes = Cave::from_rle_bytes(&bytes).unwrap();
+                assert_eq!(from_bytes.to_text(), text);
+                assert_eq!(from_bytes.resting_count() + from_bytes.fill(), total);
+            }
+        }
+        #[test]
+        fn load_hand_edited_cave() {
+            let text = "origin 498,0\nfloor solid 3\nabyss 0\ntick 0\nsource 500,0,1\nfall down,down-left,down-right\n\n..+..\n.#...\n";
+            let mut cave = Cave::from_text(text).unwrap();
+            assert_eq!(cave.fill(), 7);
+            assert!(Cave::from_text("origin 0,0\n\n.").is_err());
+            assert!(Cave::from_text("origin 0,0\nsource 0,0\n\n.x").is_err());
+            assert!(Cave::from_rle_bytes(b"AOC14RLE\x00").is_err());
+        }
+        #[test]
+        fn load_cave_without_optional_headers() {
+            let text = "origin 498,0\nsource 500,0\n\n..+..\n.....\n#...#\n#####\n";
+            let mut cave = Cave::from_text(text).unwrap();
+            assert_eq!(cave.fill(), 7);
+            assert!(cave.first_overflow().is_none());
+        }
+        #[test]
+        fn reject_oversized_rle() {
+            let rock_paths = vec![vec![Vector2D::new(499, 3), Vector2D::new(501, 3)]];
+            let cave = Cave::from_rock_paths(&rock_paths, Vector2D::new(500, 0), FloorMode::Abyss);
+            let mut bytes = cave.to_rle_bytes();
+            bytes[24..32].copy_from_slice(&i64::MAX.to_le_bytes());
+            bytes[32..40].copy_from_slice(&i64::MAX.to_le_bytes());
+            assert_eq!(Cave::from_rle_bytes(&bytes).err(), Some("Cave too large"));
+            bytes[24..32].copy_from_slice(&(1i64 << 31).to_le_bytes());
+            bytes[32..40].copy_from_slice(&(1i64 << 31).to_le_bytes());
+            assert_eq!(Cave::from_rle_bytes(&bytes).err(), Some("Cave too large"));
+        }
+        #[test]
+        fn reject_malformed_rle() {
+            let rock_paths = vec![vec![Vector2D::new(499, 3), Vector2D::new(501, 3)]];
+            let cave = Cave::from_rock_paths(&rock_paths, Vector2D::new(500, 0), FloorMode::Abyss);
+            let bytes = cave.to_rle_bytes();
+            assert!(Cave::from_rle_bytes(&bytes).is_ok());
+
+            let mut edited = bytes.clone();
+            edited[8..16].copy_from_slice(&(i64::MAX - 1).to_le_bytes());
+            assert_eq!(
+                Cave::from_rle_bytes(&edited).err(),
+                Some("Cave out of range")
+            );
+
+            let mut edited = bytes.clone();
+            edited[48..56].copy_from_slice(&2i64.to_le_bytes());
+            assert_eq!(Cave::from_rle_bytes(&edited).err(), Some("Invalid floor"));
+
+            let mut edited = bytes.clone();
+            edited[64..72].copy_from_slice(&7i64.to_le_bytes());
+            assert_eq!(
+                Cave::from_rle_bytes(&edited).err(),
+                Some("Invalid falling grain")
+            );
+
+            let mut edited = bytes.clone();
+            edited.push(0);
+            assert_eq!(
+                Cave::from_rle_bytes(&edited).err(),
+                Some("Trailing data after the last run")
+            );
+
+            let mut edited = bytes;
+            let last_run = edited.len() - 5;
+            edited[last_run..last_run + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+            assert_eq!(
+                Cave::from_rle_bytes(&edited).err(),
+                Some("Run past the end of the cave")
+            );
+        }
+        #[test]
+        fn sand_flows_left_of_zero() {
+            let rock_paths = vec![vec![Vector2D::new(0, 2), Vector2D::new(1, 2)]];
+            let mut cave =
+                Cave::from_rock_paths(&rock_paths, Vector2D::new(0, 0), FloorMode::Abyss);
+            assert_eq!(cave.step(), Step::Falling(Vector2D::new(0, 0)));
+            assert_eq!(cave.step(), Step::Falling(Vector2D::new(0, 1)));
+            assert_eq!(cave.step(), Step::Falling(Vector2D::new(-1, 2)));
+            assert_eq!(cave.step(), Step::Abyss);
+
+            let mut cave =
+                Cave::from_rock_paths(&rock_paths, Vector2D::new(0, 0), FloorMode::Solid(4));
+            assert_eq!(cave.count_reachable(), Some(14));
+            assert_eq!(cave.fill(), 14);
+        }
+        #[test]
+        fn coordinates_beyond_1000() {
+            let rock_paths = vec![vec![
+                Vector2D::new(1498, 1198),
+                Vector2D::new(1498, 1200),
+                Vector2D::new(1502, 1200),
+                Vector2D::new(1502, 1198),
+            ]];
+            let mut cave =
+                Cave::from_rock_paths(&rock_paths, Vector2D::new(1500, 1000), FloorMode::Abyss);
+            assert_eq!(count_resting(&mut cave), 10);
+        }
+        impl<T: Default> CellStore<T> for HashMap<Vector2D, T> {
+            fn get(&self, at: Vector2D) -> Option<&T> {
+                HashMap::get(self, &at)
+            }
+            fn get_mut(&mut self, at: Vector2D) -> &mut T {
+                self.entry(at).or_default()
+            }
+            fn iter<'a>(&'a self) -> impl Iterator<Item = (Vector2D, &'a T)>
+            where
+                T: 'a,
+            {
+                HashMap::iter(self).map(|(&at, cell)| (at, cell))
+            }
+        }
+
+        // Counts every byte requested, so run the benchmark on its own.
+        struct CountingAlloc;
+        static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+        unsafe impl GlobalAlloc for CountingAlloc {
+            unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+                ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+                System.alloc(layout)
+            }
+            unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+                System.dealloc(ptr, layout)
+            }
+            unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+                ALLOCATED.fetch_add(new_size, Ordering::Relaxed);
+                System.realloc(ptr, layout, new_size)
+            }
+        }
+        #[global_allocator]
+        static GLOBAL: CountingAlloc = CountingAlloc;
+
+        fn bench_fill<S: CellStore<CellState>>(
+            rock_paths: &[Vec<Vector2D>],
+            depth: i64,
+        ) -> (usize, std::time::Duration, usize) {
+            let allocated = ALLOCATED.load(Ordering::Relaxed);
+            let now = std::time::Instant::now();
+            let mut cave = CaveWith::<S>::from_rock_paths(
+                rock_paths,
+                Vector2D::new(500, 0),
+                FloorMode::Solid(depth),
+            );
+            let count = cave.fill();
+            let elapsed = now.elapsed();
+            (
+                count,
+                elapsed,
+                ALLOCATED.load(Ordering::Relaxed) - allocated,
+            )
+        }
+
+        #[test]
+        #[ignore]
+        fn bench_flat_grid_against_hash_map() {
+            // Staggered ledges above a deep floor, so the pile spans about 2000 columns.
+            let depth = 1000;
+            let rock_paths: Vec<_> = (1..depth / 20)
+                .map(|i| {
+                    let x = 500 + (i * 37) % 400 - 200;
+                    vec![Vector2D::new(x, i * 20), Vector2D::new(x + i % 30, i * 20)]
+                })
+                .collect();
+            let expected =
+                Cave::from_rock_paths(&rock_paths, Vector2D::new(500, 0), FloorMode::Solid(depth))
+                    .count_reachable();
+
+            let (flat, flat_elapsed, flat_bytes) =
+                bench_fill::<FlatGrid<CellState>>(&rock_paths, depth);
+            let (hashed, hashed_elapsed, hashed_bytes) =
+                bench_fill::<HashMap<Vector2D, CellState>>(&rock_paths, depth);
+
+            assert_eq!(Some(flat), expected);
+            assert_eq!(flat, hashed);
+            println!(
+                "FlatGrid: {:?}, {} bytes allocated; HashMap: {:?}, {} bytes allocated; {} grains",
+                flat_elapsed, flat_bytes, hashed_elapsed, hashed_bytes, flat
+            );
+        }
+    }
+}
+
+mod report {
+    use super::cave::Cave;
+    use std::str::FromStr;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Table {
+        Columns,
+        Depth,
+        Overflow,
+    }
+    impl FromStr for Table {
+        type Err = &'static str;
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "columns" => Ok(Table::Columns),
+                "depth" => Ok(Table::Depth),
+                "overflow" => Ok(Table::Overflow),
+                _ => Err("Unknown table"),
+            }
+        }
+    }
+
+    pub fn csv(cave: &Cave, table: Table) -> String {
+        let mut result = vec![];
+        match table {
+            Table::Columns => {
+                result.push("x,top,grains".to_string());
+                for column in cave.columns() {
+                    result.push(format!("{},{},{}", column.x, column.top, column.grains));
+                }
+            }
+            Table::Depth => {
+                result.push("y,grains".to_string());
+                for (y, grains) in cave.depth_histogram() {
+                    result.push(format!("{},{}", y, grains));
+                }
+            }
+            Table::Overflow => {
+                result.push("grain,step,x,y".to_string());
+                if let Some(overflow) = cave.first_overflow() {
+                    for (i, at) in overflow.path.iter().enumerate() {
+                        result.push(format!("{},{},{},{}", overflow.grain, i, at.x, at.y));
+                    }
+                }
+            }
+        }
+        result.join("\n") + "\n"
+    }
+
+    pub fn summary(cave: &Cave) -> String {
+        let mut result = vec![format!("Resting sand: {}", cave.resting_count())];
+        if let Some(pile) = cave.largest_pile() {
+            result.push(format!(
+                "Largest pile: {} grains from {},{} to {},{}",
+                pile.grains,
+                pile.bounds.min.x,
+                pile.bounds.min.y,
+                pile.bounds.max.x,
+                pile.bounds.max.y
+            ));
+        }
+        if let Some(column) = cave.columns().iter().max_by_key(|c| c.grains) {
+            result.push(format!(
+                "Fullest column: x={} with {} grains, top at y={}",
+                column.x, column.grains, column.top
+            ));
+        }
+        if let Some(overflow) = cave.first_overflow() {
+            let end = overflow.path.last().unwrap();
+            result.push(format!(
+                "First overflow: grain {} after {} moves, leaving at {},{}",
+                overflow.grain,
+                overflow.path.len() - 1,
+                end.x,
+                end.y
+            ));
+        }
+        result.join("\n")
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::super::{
+            cave::FloorMode,
+            coordinate::{BoundingBox, Vector2D},
+        };
+        use super::*;
+
+        fn filled_sample() -> Cave {
+            let input = include_str!("../../input/14-sample.txt");
+            let rock_paths = crate::rock_path::parse_rock_paths(input, false).unwrap();
+            let mut cave =
+                Cave::from_rock_paths(&rock_paths, Vector2D::new(500, 0), FloorMode::Abyss);
+            cave.fill();
+            cave
+        }
+
+        #[test]
+        fn sample_statistics() {
+            let cave = filled_sample();
+            let depth = cave.depth_histogram();
+            assert_eq!(
+                depth.into_iter().collect::<Vec<_>>(),
+                vec![(2, 1), (3, 3), (4, 3), (5, 4), (6, 3), (7, 4), (8, 6)]
+            );
+            let pile = cave.largest_pile().unwrap();
+            assert_eq!(pile.grains, 22);
+            assert_eq!(
+                pile.bounds,
+                BoundingBox::new(Vector2D::new(497, 2), Vector2D::new(501, 8))
+            );
+            let columns = cave.columns();
+            assert_eq!(
+                columns.first().map(|c| (c.x, c.top, c.grains)),
+                Some((495, 8, 1))
+            );
+            assert_eq!(columns.iter().map(|c| c.grains).sum::<usize>(), 24);
+        }
+        #[test]
+        fn overflow_path_matches_step_mode() {
+            let cave = filled_sample();
+            let overflow = cave.first_overflow().unwrap();
+            assert_eq!(overflow.grain, 25);
+            assert_eq!(overflow.path.len(), 10);
+            assert_eq!(overflow.path[0], Vector2D::new(500, 0));
+            assert_eq!(overflow.path[9], Vector2D::new(493, 9));
+
+            let input = include_str!("../../input/14-sample.txt");
+            let rock_paths = crate::rock_path::parse_rock_paths(input, false).unwrap();
+            let mut stepped =
+                Cave::from_rock_paths(&rock_paths, Vector2D::new(500, 0), FloorMode::Abyss);
+            while stepped.advance() {}
+            assert_eq!(stepped.first_overflow(), Some(overflow));
+        }
+        #[test]
+        fn csv_tables() {
+            let cave = filled_sample();
+            let depth = csv(&cave, Table::Depth);
+            assert!(depth.starts_with("y,grains\n2,1\n3,3\n"));
+            let overflow = csv(&cave, Table::Overflow);
+            assert_eq!(overflow.lines().count(), 11);
+            assert_eq!(overflow.lines().nth(1), Some("25,0,500,0"));
+            assert!(csv(&cave, Table::Columns).starts_with("x,top,grains\n495,8,1\n"));
+        }
+    }
+}
+
+mod render {
+    use super::{
+        cave::Cave,
+        coordinate::{BoundingBox, Vector2D},
+    };
+    use std::str::FromStr;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum FrameFormat {
+        Ansi,
+        Text,
+        Ppm,
+    }
+    impl FromStr for FrameFormat {
+        type Err = &'static str;
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "ansi" => Ok(FrameFormat::Ansi),
+                "text" => Ok(FrameFormat::Text),
+                "ppm" => Ok(FrameFormat::Ppm),
+                _ => Err("Unknown frame format"),
+            }
+        }
+    }
+    impl FrameFormat {
+        pub fn extension(&self) -> &'static str {
+            match self {
+                FrameFormat::Ansi => "ans",
+                FrameFormat::Text => "txt",
+                FrameFormat::Ppm => "ppm",
+            }
+        }
+    }
+
+    pub struct Renderer {
+        bounds: BoundingBox,
+        format: FrameFormat,
+    }
+    impl Renderer {
+        pub fn new(cave: &Cave, margin: i64, format: FrameFormat) -> Self {
+            Self {
+                bounds: cave.extent().expand(margin),
+                format,
+            }
+        }
+        pub fn shows(&self, at: Vector2D) -> bool {
+            self.bounds.contains(at)
+        }
+        fn rows<'a>(&'a self, cave: &'a Cave) -> impl Iterator<Item = Vec<char>> + 'a {
+            let BoundingBox { min, max } = self.bounds;
+            (min.y..=max.y).map(move |y| {
+                (min.x..=max.x)
+                    .map(|x| cave.char_at(Vector2D::new(x, y)))
+                    .collect()
+            })
+        }
+        pub fn render(&self, cave: &Cave) -> Vec<u8> {
+            match self.format {
+                FrameFormat::Text => {
+                    let mut result = String::new();
+                    for row in self.rows(cave) {
+                        result.extend(row);
+                        result.push('\n');
+                    }
+                    result.push('\n');
+                    result.into_bytes()
+                }
+                FrameFormat::Ansi => {
+                    let mut result = String::from("\x1b[H");
+                    for row in self.rows(cave) {
+                        for c in row {
+                            let color = match c {
+                                '#' => "90",
+                                'o' => "33",
+                                '~' => "31",
+                                '+' => "32",
+                                _ => "0",
+                            };
+                            result.push_str(&format!("\x1b[{}m{}", color, c));
+                        }
+                        result.push_str("\x1b[0m\n");
+                    }
+                    result.into_bytes()
+                }
+                FrameFormat::Ppm => {
+                    let size = Vector2D::new(self.bounds.width(), self.bounds.height())
+                        .into_index()
+                        .unwrap();
+                    let (width, height) = (size.x, size.y);
+                    let mut result = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+                    for row in self.rows(cave) {
+                        for c in row {
+                            let rgb: [u8; 3] = match c {
+                                '#' => [110, 110, 120],
+                                'o' => [230, 200, 90],
+                                '~' => [240, 110, 40],
+                                '+' => [80, 200, 80],
+                                _ => [20, 20, 30],
+                            };
+                            result.extend(rgb);
+                        }
+                    }
+                    result
+                }
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::super::cave::FloorMode;
+        use super::*;
+
+        fn sample_cave() -> Cave {
+            let input = include_str!("../../input/14-sample.txt");
+            let rock_paths = crate::rock_path::parse_rock_paths(input, false).unwrap();
+            Cave::from_rock_paths(&rock_paths, Vector2D::new(500, 0), FloorMode::Abyss)
+        }
+
+        #[test]
+        fn render_text_matches_puzzle() {
+            let mut cave = sample_cave();
+            let renderer = Renderer::new(&cave, 0, FrameFormat::Text);
+            let expected = [
+                "......+...",
+                "..........",
+                "..........",
+                "..........",
+                "....#...##",
+                "....#...#.",
+                "..###...#.",
+                "........#.",
+                "........#.",
+                "#########.",
+            ];
+            let frame = String::from_utf8(renderer.render(&cave)).unwrap();
+            assert_eq!(frame, format!("{}\n\n", expected.join("\n")));
+
+            for _ in 0..5 {
+                cave.advance();
+            }
+            let frame = String::from_utf8(renderer.render(&cave)).unwrap();
+            assert_eq!(frame.lines().nth(7), Some("......o.#."));
+            assert_eq!(frame.lines().nth(8), Some("....oooo#."));
+        }
+        #[test]
+        fn render_ppm_with_margin() {
+            let cave = sample_cave();
+            let renderer = Renderer::new(&cave, 1, FrameFormat::Ppm);
+            let frame = renderer.render(&cave);
+            let header = b"P6\n12 12\n255\n";
+            assert_eq!(&frame[..header.len()], header);
+            assert_eq!(frame.len(), header.len() + 12 * 12 * 3);
+        }
+    }
+}
+
+mod grid {
+    use super::coordinate::{BoundingBox, Vector2D};
+
+    // Cells are kept row by row in a single allocation. Writing outside the
+    // current bounds regrows the grid with some slack around the new cell.
+    pub struct FlatGrid<T> {
+        bounds: Option<BoundingBox>,
+        stride: usize,
+        cells: Vec<T>,
+    }
+    // Where a cave keeps its cells. `get` is `None` for cells never written.
+    pub trait CellStore<T>: Default {
+        fn get(&self, at: Vector2D) -> Option<&T>;
+        fn get_mut(&mut self, at: Vector2D) -> &mut T;
+        fn iter<'a>(&'a self) -> impl Iterator<Item = (Vector2D, &'a T)>
+        where
+            T: 'a;
+    }
+    impl<T: Copy + Default> FlatGrid<T> {
+        pub fn new() -> Self {
+            Self {
+                bounds: None,
+                stride: 0,
+                cells: vec![],
+            }
+        }
+        fn index(&self, at: Vector2D) -> Option<usize> {
+            let bounds = self.bounds?;
+            if !bounds.contains(at) {
+                return None;
+            }
+            let offset = (at - bounds.min).into_index().ok()?;
+            Some(offset.y * self.stride + offset.x)
+        }
+        fn grow(&mut self, at: Vector2D) {
+            let bounds = match self.bounds {
+                Some(old) => {
+                    let slack = (old.width().max(old.height()) / 2).max(8);
+                    old.union(&BoundingBox::new(at, at).expand(slack))
+                }
+                None => BoundingBox::new(at, at).expand(8),
+            };
+            let stride = bounds.width() as usize;
+            let mut cells = vec![T::default(); stride * bounds.height() as usize];
+            for (at, &cell) in self.iter() {
+                let offset = (at - bounds.min).into_index().unwrap();
+                cells[offset.y * stride + offset.x] = cell;
+            }
+            self.bounds = Some(bounds);
+            self.stride = stride;
+            self.cells = cells;
+        }
+    }
+    impl<T: Copy + Default> Default for FlatGrid<T> {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+    impl<T: Copy + Default> CellStore<T> for FlatGrid<T> {
+        fn get(&self, at: Vector2D) -> Option<&T> {
+            self.index(at).map(|i| &self.cells[i])
+        }
+        fn get_mut(&mut self, at: Vector2D) -> &mut T {
+            let i = match self.index(at) {
+                Some(i) => i,
+                None => {
+                    self.grow(at);
+                    self.index(at).unwrap()
+                }
+            };
+            &mut self.cells[i]
+        }
+        fn iter<'a>(&'a self) -> impl Iterator<Item = (Vector2D, &'a T)>
+        where
+            T: 'a,
+        {
+            self.bounds
+                .into_iter()
+                .flat_map(|bounds| bounds.cells())
+                .zip(self.cells.iter())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn grows_and_keeps_cells() {
+            let mut grid: FlatGrid<u8> = FlatGrid::new();
+            assert_eq!(grid.get(Vector2D::new(0, 0)), None);
+            *grid.get_mut(Vector2D::new(500, 0)) = 1;
+            *grid.get_mut(Vector2D::new(-40, 90)) = 2;
+            *grid.get_mut(Vector2D::new(1500, 1200)) = 3;
+            assert_eq!(grid.get(Vector2D::new(500, 0)), Some(&1));
+            assert_eq!(grid.get(Vector2D::new(-40, 90)), Some(&2));
+            assert_eq!(grid.get(Vector2D::new(1500, 1200)), Some(&3));
+            assert_eq!(grid.get(Vector2D::new(499, 0)), Some(&0));
+            let mut set: Vec<_> = grid.iter().filter(|(_at, &v)| v != 0).collect();
+            set.sort();
+            assert_eq!(
+                set,
+                vec![
+                    (Vector2D::new(-40, 90), &2),
+                    (Vector2D::new(500, 0), &1),
+                    (Vector2D::new(1500, 1200), &3)
+                ]
+            );
+        }
+    }
+}
+
+mod coordinate {
+    use std::{
+        borrow::Borrow,
+        cmp::Ordering,
+        ops::{Add, AddAssign, Mul, Neg, Sub},
+    };
+
+    #[derive(Debug, PartialEq, Clone, Copy, Eq, PartialOrd, Ord, Hash)]
+    pub struct Vector2D<T = i64> {
+        pub x: T,
+        pub y: T,
+    }
+    impl<T> Vector2D<T> {
+        pub fn new(x: T, y: T) -> Self {
+            Self { x, y }
+        }
+    }
+    impl<T: Copy + Ord + From<i8>> Vector2D<T> {
+        pub fn sig_vec(&self, other: &Self) -> Self {
+            let dx = signum(self.x, other.x);
+            let dy = signum(self.y, other.y);
+            Self::new(dx, dy)
+        }
+    }
+    impl<T: Copy + Ord + Neg<Output = T> + Add<Output = T>> Vector2D<T> {
+        pub fn manhattan(&self) -> T {
+            self.x.max(-self.x) + self.y.max(-self.y)
+        }
+        pub fn chebyshev(&self) -> T {
+            self.x.max(-self.x).max(self.y.max(-self.y))
+        }
+    }
+    impl<T: TryInto<usize>> Vector2D<T> {
+        pub fn into_index(self) -> Result<Vector2D<usize>, &'static str> {
+            match (self.x.try_into(), self.y.try_into()) {
+                (Ok(x), Ok(y)) => Ok(Vector2D::new(x, y)),
+                _ => Err("Coordinate outside the grid"),
+            }
+        }
+    }
+    impl<T: AddAssign> AddAssign for Vector2D<T> {
+        fn add_assign(&mut self, rhs: Self) {
+            self.x += rhs.x;
+            self.y += rhs.y;
+        }
+    }
+    impl<T: Add<Output = T>> Add for Vector2D<T> {
+        type Output = Self;
+
+        fn add(self, rhs: Self) -> Self::Output {
+            Self::new(self.x + rhs.x, self.y + rhs.y)
+        }
+    }
+    impl<T: Sub<Output = T>> Sub for Vector2D<T> {
+        type Output = Self;
+
+        fn sub(self, rhs: Self) -> Self::Output {
+            Self::new(self.x - rhs.x, self.y - rhs.y)
+        }
+    }
+    impl<T: Neg<Output = T>> Neg for Vector2D<T> {
+        type Output = Self;
+
+        fn neg(self) -> Self::Output {
+            Self::new(-self.x, -self.y)
+        }
+    }
+    impl<T: Copy + Mul<Output = T>> Mul<T> for Vector2D<T> {
+        type Output = Self;
+
+        fn mul(self, rhs: T) -> Self::Output {
+            Self::new(self.x * rhs, self.y * rhs)
+        }
+    }
+    fn signum<T: Ord + From<i8>>(a: T, b: T) -> T {
+        match a.cmp(&b) {
+            Ordering::Less => T::from(-1),
+            Ordering::Equal => T::from(0),
+            Ordering::Greater => T::from(1),
+        }
+    }
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct BoundingBox {
+        pub min: Vector2D,
+        pub max: Vector2D,
+    }
+    impl BoundingBox {
+        pub fn new(min: Vector2D, max: Vector2D) -> Self {
+            Self { min, max }
+        }
+        pub fn from_points<I>(points: I) -> Option<Self>
+        where
+            I: IntoIterator,
+            I::Item: Borrow<Vector2D>,
+        {
+            let mut points = points.into_iter();
+            let first = *points.next()?.borrow();
+            Some(points.fold(Self::new(first, first), |bounds, at| {
+                bounds.union(&Self::new(*at.borrow(), *at.borrow()))
+            }))
+        }
+        pub fn contains(&self, at: Vector2D) -> bool {
+            (self.min.x..=self.max.x).contains(&at.x) && (self.min.y..=self.max.y).contains(&at.y)
+        }
+        pub fn union(&self, other: &Self) -> Self {
+            Self::new(
+                Vector2D::new(self.min.x.min(other.min.x), self.min.y.min(other.min.y)),
+                Vector2D::new(self.max.x.max(other.max.x), self.max.y.max(other.max.y)),
+            )
+        }
+        pub fn expand(&self, margin: i64) -> Self {
+            let margin = Vector2D::new(margin, margin);
+            Self::new(self.min - margin, self.max + margin)
+        }
+        pub fn width(&self) -> i64 {
+            self.max.x - self.min.x + 1
+        }
+        pub fn height(&self) -> i64 {
+            self.max.y - self.min.y + 1
+        }
+        pub fn cells(&self) -> impl Iterator<Item = Vector2D> {
+            let Self { min, max } = *self;
+            (min.y..=max.y).flat_map(move |y| (min.x..=max.x).map(move |x| Vector2D::new(x, y)))
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn arithmetic() {
+            let a = Vector2D::new(3, -2);
+            let b = Vector2D::new(-1, 5);
+            assert_eq!(a + b, Vector2D::new(2, 3));
+            assert_eq!(a - b, Vector2D::new(4, -7));
+            assert_eq!(-a, Vector2D::new(-3, 2));
+            assert_eq!(a * 3, Vector2D::new(9, -6));
+            let mut c = a;
+            c += b;
+            assert_eq!(c, a + b);
+        }
+        #[test]
+        fn norms() {
+            let v = Vector2D::new(-3i64, 4);
+            assert_eq!(v.manhattan(), 7);
+            assert_eq!(v.chebyshev(), 4);
+            assert_eq!(Vector2D::new(0i32, 0).manhattan(), 0);
+        }
+        #[test]
+        fn sig_vec_points_towards_self() {
+            let from = Vector2D::new(500, 4);
+            assert_eq!(Vector2D::new(498, 4).sig_vec(&from), Vector2D::new(-1, 0));
+            assert_eq!(Vector2D::new(500, 9).sig_vec(&from), Vector2D::new(0, 1));
+            assert_eq!(from.sig_vec(&from), Vector2D::new(0, 0));
+        }
+        #[test]
+        fn bounding_box() {
+            let points = vec![
+                Vector2D::new(3, 4),
+                Vector2D::new(1, 6),
+                Vector2D::new(2, 5),
+            ];
+            let bounds = BoundingBox::from_points(&points).unwrap();
+            assert_eq!(
+                bounds,
+                BoundingBox::from_points(points.iter().copied()).unwrap()
+            );
+            assert_eq!(
+                bounds,
+                BoundingBox::new(Vector2D::new(1, 4), Vector2D::new(3, 6))
+            );
+            assert_eq!((bounds.width(), bounds.height()), (3, 3));
+            assert!(bounds.contains(Vector2D::new(1, 6)));
+            assert!(!bounds.contains(Vector2D::new(0, 5)));
+            assert_eq!(bounds.cells().count(), 9);
+            assert_eq!(bounds.cells().nth(1), Some(Vector2D::new(2, 4)));
+            assert_eq!(
+                bounds.expand(1),
+                BoundingBox::new(Vector2D::new(0, 3), Vector2D::new(4, 7))
+            );
+            let other = BoundingBox::new(Vector2D::new(-2, 5), Vector2D::new(0, 9));
+            assert_eq!(
+                bounds.union(&other),
+                BoundingBox::new(Vector2D::new(-2, 4), Vector2D::new(3, 9))
+            );
+            assert_eq!(BoundingBox::from_points(Vec::<Vector2D>::new()), None);
+        }
+        #[test]
+        fn checked_index_conversion() {
+            assert_eq!(Vector2D::new(2i64, 7).into_index(), Ok(Vector2D::new(2, 7)));
+            assert!(Vector2D::new(-1i64, 7).into_index().is_err());
+            assert!(Vector2D::new(0i64, -1).into_index().is_err());
+        }
+    }
+}
+
+mod rock_path {
+    use super::{coordinate::Vector2D, parser::vector_2d_path};
+    use nom::combinator::all_consuming;
+    use std::fmt;
+
+    #[derive(Debug, PartialEq, Eq)]
+    pub enum PathError {
+        Empty,
+        EmptyLine(usize),
+        InvalidLine(usize),
+        SinglePoint(usize, Vector2D),
+        Diagonal(usize, Vector2D, Vector2D),
+        Skewed(usize, Vector2D, Vector2D),
+    }
+    impl fmt::Display for PathError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                PathError::Empty => write!(f, "No rock paths"),
+                PathError::EmptyLine(line) => write!(f, "Line {}: empty line", line),
+                PathError::InvalidLine(line) => write!(f, "Line {}: invalid rock path", line),
+                PathError::SinglePoint(line, at) => {
+                    write!(
+                        f,
+                        "Line {}: path has only one point {},{}",
+                        line, at.x, at.y
+                    )
+                }
+                PathError::Diagonal(line, a, b) => write!(
+                    f,
+                    "Line {}: diagonal segment {},{} -> {},{}",
+                    line, a.x, a.y, b.x, b.y
+                ),
+                PathError::Skewed(line, a, b) => write!(
+                    f,
+                    "Line {}: segment {},{} -> {},{} is neither straight nor diagonal",
+                    line, a.x, a.y, b.x, b.y
+                ),
+            }
+        }
+    }
+
+    pub fn parse_rock_paths(
+        input: &str,
+        allow_diagonal: bool,
+    ) -> Result<Vec<Vec<Vector2D>>, PathError> {
+        let mut result = vec![];
+        for (i, line) in input.lines().enumerate() {
+            let line_number = i + 1;
+            if line.trim().is_empty() {
+                return Err(PathError::EmptyLine(line_number));
+            }
+            let path = match all_consuming(vector_2d_path)(line.trim_end()) {
+                Ok((_, path)) => path,
+                Err(_) => return Err(PathError::InvalidLine(line_number)),
+            };
+            if let [at] = path[..] {
+                return Err(PathError::SinglePoint(line_number, at));
+            }
+            for segment in path.windows(2) {
+                let (a, b) = (segment[0], segment[1]);
+                let d = b - a;
+                if d.manhattan() == d.chebyshev() {
+                    continue;
+                }
+                if d.manhattan() != 2 * d.chebyshev() {
+                    return Err(PathError::Skewed(line_number, a, b));
+                }
+                if !allow_diagonal {
+                    return Err(PathError::Diagonal(line_number, a, b));
+                }
+            }
+            result.push(path);
+        }
+        match result.is_empty() {
+            true => Err(PathError::Empty),
+            false => Ok(result),
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn sample_is_valid() {
+            let input = include_str!("../../input/14-sample.txt");
+            let paths = parse_rock_paths(input, false).unwrap();
+            assert_eq!(paths.len(), 2);
+            assert_eq!(paths[1].len(), 4);
+        }
+        #[test]
+        fn reports_line_numbers() {
+            assert_eq!(parse_rock_paths("", false), Err(PathError::Empty));
+            assert_eq!(
+                parse_rock_paths("1,2 -> 1,4\n\n3,4 -> 5,4\n", false),
+                Err(PathError::EmptyLine(2))
+            );
+            assert_eq!(
+                parse_rock_paths("1,2 -> 1,4\n3,4 -> x\n", false),
+                Err(PathError::InvalidLine(2))
+            );
+            assert_eq!(
+                parse_rock_paths("1,2 -> 1,4\n1,1 -> 3,1\n7,7\n", false),
+                Err(PathError::SinglePoint(3, Vector2D::new(7, 7)))
+            );
+            assert_eq!(
+                parse_rock_paths("1,2 -> 3,4\n", false),
+                Err(PathError::Diagonal(
+                    1,
+                    Vector2D::new(1, 2),
+                    Vector2D::new(3, 4)
+                ))
+            );
+            assert_eq!(
+                parse_rock_paths("1,2 -> 1,4 -> 2,6\n", true),
+                Err(PathError::Skewed(
+                    1,
+                    Vector2D::new(1, 4),
+                    Vector2D::new(2, 6)
+                ))
+            );
+        }
+        #[test]
+        fn no_trailing_empty_path() {
+            assert_eq!(
+                parse_rock_paths("1,2 -> 3,4 -> 5,6\n7,8 -> 9,10\n", true),
+                Ok(vec![
+                    vec![
+                        Vector2D { x: 1, y: 2 },
+                        Vector2D { x: 3, y: 4 },
+                        Vector2D { x: 5, y: 6 }
+                    ],
+                    vec![Vector2D { x: 7, y: 8 }, Vector2D { x: 9, y: 10 }],
+                ])
+            );
+        }
+        #[test]
+        fn diagonals_on_request() {
+            let paths = parse_rock_paths("1,2 -> 3,4 -> 3,0\n", true).unwrap();
+            assert_eq!(
+                paths,
+                vec![vec![
+                    Vector2D::new(1, 2),
+                    Vector2D::new(3, 4),
+                    Vector2D::new(3, 0)
+                ]]
+            );
+        }
+    }
+}
+
+mod parser {
+    use super::coordinate::Vector2D;
+    use nom::{
+        bytes::complete::tag,
+        character::complete::digit1,
+        combinator::{map, map_res},
+        multi::separated_list0,
+        sequence::{preceded, tuple},
+        IResult,
+    };
+
+    fn parsed_i64(input: &str) -> IResult<&str, i64> {
+        map_res(digit1, |s: &str| s.parse::<i64>())(input)
     }
     fn vector_2d(input: &str) -> IResult<&str, Vector2D> {
         map(
-            tuple((parsed_usize, preceded(tag(","), parsed_usize))),
+            tuple((parsed_i64, preceded(tag(","), parsed_i64))),
             |(x, y)| Vector2D { x, y },
         )(input)
     }
-    fn vector_2d_path(input: &str) -> IResult<&str, Vec<Vector2D>> {
+    pub fn vector_2d_path(input: &str) -> IResult<&str, Vec<Vector2D>> {
         separated_list0(tag(" -> "), vector_2d)(input)
     }
-    pub fn vector_2d_paths(input: &str) -> IResult<&str, Vec<Vec<Vector2D>>> {
-        separated_list0(tag("\n"), vector_2d_path)(input)
-    }
 
     #[cfg(test)]
     mod tests {
@@ -274,23 +2209,5 @@ mod parser {
                 ))
             );
         }
-        #[test]
-        fn test_vector_2d_paths() {
-            assert_eq!(
-                vector_2d_paths("1,2 -> 3,4 -> 5,6\n7,8 -> 9,10\n"),
-                Ok((
-                    "",
-                    vec![
-                        vec![
-                            Vector2D { x: 1, y: 2 },
-                            Vector2D { x: 3, y: 4 },
-                            Vector2D { x: 5, y: 6 }
-                        ],
-                        vec![Vector2D { x: 7, y: 8 }, Vector2D { x: 9, y: 10 },],
-                        vec![]
-                    ]
-                ))
-            );
-        }
     }
 }
@@ -0,0 +1,79 @@
+// A closed interval `start..=end` that is never empty.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct InclusiveRange<T> {
+    start: T,
+    end: T,
+}
+impl<T: Copy + Ord> InclusiveRange<T> {
+    pub fn new(start: T, end: T) -> Option<Self> {
+        match start <= end {
+            true => Some(Self { start, end }),
+            false => None,
+        }
+    }
+    pub fn start(&self) -> T {
+        self.start
+    }
+    pub fn end(&self) -> T {
+        self.end
+    }
+    pub fn contains_value(&self, value: T) -> bool {
+        self.start <= value && value <= self.end
+    }
+    pub fn contains(&self, other: &Self) -> bool {
+        self.start <= other.start && other.end <= self.end
+    }
+    pub fn overlaps(&self, other: &Self) -> bool {
+        self.start <= other.end && other.start <= self.end
+    }
+    pub fn intersection(&self, other: &Self) -> Option<Self> {
+        Self::new(self.start.max(other.start), self.end.min(other.end))
+    }
+    // Only overlapping ranges have a union that is itself a range.
+    pub fn union(&self, other: &Self) -> Option<Self> {
+        match self.overlaps(other) {
+            true => Self::new(self.start.min(other.start), self.end.max(other.end)),
+            false => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(start: u32, end: u32) -> InclusiveRange<u32> {
+        InclusiveRange::new(start, end).unwrap()
+    }
+
+    #[test]
+    fn test_new() {
+        assert_eq!(
+            InclusiveRange::new(3, 3).map(|r| (r.start(), r.end())),
+            Some((3, 3))
+        );
+        assert_eq!(InclusiveRange::new(4, 3), None);
+    }
+    #[test]
+    fn test_contains() {
+        assert!(range(2, 8).contains(&range(3, 7)));
+        assert!(range(3, 7).contains(&range(3, 7)));
+        assert!(!range(3, 7).contains(&range(2, 8)));
+        assert!(range(2, 8).contains_value(8));
+        assert!(!range(2, 8).contains_value(9));
+    }
+    #[test]
+    fn test_overlaps() {
+        assert!(range(5, 7).overlaps(&range(7, 9)));
+        assert!(range(7, 9).overlaps(&range(5, 7)));
+        assert!(!range(2, 3).overlaps(&range(4, 5)));
+    }
+    #[test]
+    fn test_intersection_and_union() {
+        assert_eq!(range(2, 6).intersection(&range(4, 8)), Some(range(4, 6)));
+        assert_eq!(range(2, 3).intersection(&range(4, 5)), None);
+        assert_eq!(range(2, 6).union(&range(4, 8)), Some(range(2, 8)));
+        assert_eq!(range(2, 8).union(&range(3, 7)), Some(range(2, 8)));
+        assert_eq!(range(2, 3).union(&range(4, 5)), None);
+    }
+}